@@ -0,0 +1,34 @@
+use prisma_client_rust::QueryError;
+
+use crate::db::{node, node_alias, node_redirect, PrismaClient};
+
+/// Aliases are matched ignoring case and repeated whitespace, so "künstliche  intelligenz" finds "Künstliche Intelligenz"
+pub fn normalize_alias(alias: &str) -> String {
+    alias.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Canonical name of the node with the given name, alias or former name. Names take precedence over aliases
+pub async fn resolve_node_name(db: &PrismaClient, name_or_alias: &str) -> Result<Option<String>, QueryError> {
+    let node = db
+        .node()
+        .find_unique(node::name::equals(name_or_alias.to_string()))
+        .exec()
+        .await?;
+    if let Some(node) = node {
+        return Ok(Some(node.name));
+    }
+    let alias = db
+        .node_alias()
+        .find_unique(node_alias::normalized::equals(normalize_alias(name_or_alias)))
+        .exec()
+        .await?;
+    if let Some(alias) = alias {
+        return Ok(Some(alias.node_name));
+    }
+    Ok(db
+        .node_redirect()
+        .find_unique(node_redirect::old_name::equals(name_or_alias.to_string()))
+        .exec()
+        .await?
+        .map(|redirect| redirect.node_name))
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use prisma_client_rust::{Direction, QueryError};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::db::{revision, PrismaClient};
+
+/// Author of revisions that capture a row as it was before the history started
+pub const BASELINE_REVISION_AUTHOR: &str = "baseline";
+
+/// Changed fields of two rows as `{field: {"old": .., "new": ..}}`.
+/// A missing row (before a create, after a delete) is passed as null, then every field of the other one is listed
+pub fn json_diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before_fields = before.as_object().unwrap_or(&empty);
+    let after_fields = after.as_object().unwrap_or(&empty);
+    let mut diff = Map::new();
+    for field in before_fields.keys().chain(after_fields.keys()) {
+        let old = before_fields.get(field).unwrap_or(&Value::Null);
+        let new = after_fields.get(field).unwrap_or(&Value::Null);
+        if old != new && !diff.contains_key(field) {
+            diff.insert(field.clone(), json!({"old": old, "new": new}));
+        }
+    }
+    Value::Object(diff)
+}
+
+/// Stores who changed which row, with the changed fields of before and after.
+/// The actor is a user email, `apikey:<name>` or `scheduler`
+pub async fn record_audit<T: Serialize>(db: &PrismaClient, actor: &str, action: &str, entity: &str, entity_key: String, before: Option<&T>, after: Option<&T>) -> Result<(), QueryError> {
+    let before = serde_json::to_value(before).unwrap_or_default();
+    let after = serde_json::to_value(after).unwrap_or_default();
+    db
+        .audit_log()
+        .create(
+            actor.to_string(),
+            action.to_string(),
+            entity.to_string(),
+            entity_key,
+            json_diff(&before, &after),
+            vec![]
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Audit record plus revision, for the entities with revision history (nodes and articles)
+pub async fn record_revisioned_change<T: Serialize>(db: &PrismaClient, actor: &str, action: &str, entity: &str, entity_key: String, before: Option<&T>, after: Option<&T>) -> Result<(), QueryError> {
+    record_audit(db, actor, action, entity, entity_key.clone(), before, after).await?;
+    record_revision(db, actor, entity, &entity_key, before, after, false).await
+}
+
+/// Appends the row as it is after the change (deleted rows as null snapshot), or as proposed if pending.
+/// Rows that predate the history (e.g. from loadcsv) get their previous state as baseline revision first,
+/// so the very first change can be reverted as well
+pub async fn record_revision<T: Serialize>(db: &PrismaClient, actor: &str, entity: &str, entity_key: &str, before: Option<&T>, after: Option<&T>, pending: bool) -> Result<(), QueryError> {
+    let latest = db
+        .revision()
+        .find_first(vec![
+            revision::entity::equals(entity.to_string()),
+            revision::entity_key::equals(entity_key.to_string()),
+        ])
+        .order_by(revision::number::order(Direction::Desc))
+        .exec()
+        .await?;
+    let mut number = latest.map_or(0, |revision| revision.number);
+    if let (0, Some(before)) = (number, before) {
+        number += 1;
+        db
+            .revision()
+            .create(
+                entity.to_string(),
+                entity_key.to_string(),
+                number,
+                serde_json::to_value(before).unwrap_or_default(),
+                BASELINE_REVISION_AUTHOR.to_string(),
+                vec![]
+            )
+            .exec()
+            .await?;
+    }
+    db
+        .revision()
+        .create(
+            entity.to_string(),
+            entity_key.to_string(),
+            number + 1,
+            serde_json::to_value(after).unwrap_or_default(),
+            actor.to_string(),
+            vec![revision::deleted::set(after.is_none()), revision::pending::set(pending)]
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Revision snapshots keep the names they were taken with, while renameNode moves the revisions to the new key.
+/// Points a snapshot at the current key of its row, and the node names in `node_fields` at their current names
+pub fn rekey_snapshot(snapshot: &mut Value, entity_key: &str, node_fields: &[&str], current_names: &HashMap<String, String>) {
+    if let Some(fields) = snapshot.as_object_mut() {
+        fields.insert("name".to_string(), Value::String(entity_key.to_string()));
+        for field in node_fields {
+            let current = fields.get(*field).and_then(Value::as_str).and_then(|name| current_names.get(name));
+            if let Some(current) = current.cloned() {
+                fields.insert(field.to_string(), Value::String(current));
+            }
+        }
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Input fields that carry credentials: passwords, tokens, secrets, one-time and recovery codes
+fn is_secret_field(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("password") || name.contains("token") || name.contains("secret") || name.ends_with("code") || name.starts_with("recovery")
+}
+
+/// Copy of a procedure input with credential fields masked, so it can be written to the request log
+pub fn redact_secrets(input: &Value) -> Value {
+    match input {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| {
+                    let value = if is_secret_field(name) { Value::String(REDACTED.into()) } else { redact_secrets(value) };
+                    (name.clone(), value)
+                })
+                .collect()
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_secrets).collect()),
+        other => other.clone(),
+    }
+}
@@ -1,75 +1,553 @@
-use jsonwebtoken::{Header, EncodingKey, TokenData, DecodingKey};
-use prisma_client_rust::chrono::Utc;
-use ring::{rand::{self}, digest};
-use serde::{Serialize, Deserialize};
+use std::{env, fmt, str::FromStr};
 
-use crate::db::Role;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::http::{header::{AUTHORIZATION, COOKIE, ORIGIN}, HeaderMap};
+use base64::engine::fast_portable::{self, FastPortable};
+use jsonwebtoken::{Algorithm, Header, EncodingKey, TokenData, DecodingKey, errors::ErrorKind};
+use prisma_client_rust::{chrono::{Duration, Utc}, QueryError};
+use ring::{constant_time, digest, rand::{self, SecureRandom}};
+use rspc::ErrorCode;
+use serde::{de::DeserializeOwned, Serialize, Deserialize};
 
-const JWT_SECRET: &[u8; 25] = b"tempSecretThisHasToChange"; //TODO: change this for production
+use crate::db::{users, ApiKeyScope, PrismaClient, Role};
+
+const PLACEHOLDER_JWT_SECRET: &str = "tempSecretThisHasToChange";
+const PLACEHOLDER_JWT_KID: &str = "dev";
+const DEFAULT_JWT_EXPIRY_MINUTES: i64 = 15;
+const DEFAULT_REFRESH_EXPIRY_DAYS: i64 = 14;
+const MFA_TOKEN_MINUTES: i64 = 5;
+const TOKEN_BYTES: usize = 32;
+const URL_SAFE_NO_PAD: FastPortable = FastPortable::from(&base64::alphabet::URL_SAFE, fast_portable::NO_PAD);
+const BEARER_PREFIX: &str = "Bearer ";
 pub const COOKIE_SESSION_KEY: &str = "session";
-const SALT_SEP: char = '$';
+const API_KEY_PREFIX: &str = "wlk_";
+const API_KEY_DISPLAY_CHARS: usize = 12;
+const LEGACY_SALT_SEP: char = '$';
+const PHC_ARGON2_PREFIX: &str = "$argon2";
+const LOCKOUT_BASE_SECONDS: i64 = 30;
+const LOCKOUT_MAX_SECONDS: i64 = 60 * 60;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub email: String,
     pub role: Role,
+    pub sid: String,         // Session id, checked against the sessions table so tokens can be revoked
     pub exp: usize,          // Required (validate_exp defaults to true in validation). Expiration time (as UTC timestamp)
 }
 
-pub fn gen_salt() -> String {
-    //TODO: bit unsafe
-    rand::generate::<[u8; 8]>(&rand::SystemRandom::new())
-        .unwrap()
-        .expose()
-        .into_iter()
-        .map(char::from)
-        .fold("".to_string(), |mut acc, c| {
-            acc.push(c);
-            acc
-        })
+/// Issued after the password step of a login with two-factor authentication, exchanged in `loginTotp`.
+/// Shares no fields with Claims, so neither token is accepted in place of the other
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub mfa_email: String,
+    pub exp: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Sign out and manage the own account
+    OwnAccount,
+    /// Read admin data such as search statistics
+    ViewAdminData,
+    /// Create and update nodes, edges and articles
+    EditContent,
+    /// Create and update categories and disciplines
+    EditTaxonomy,
+    /// Delete nodes, edges, articles, categories and disciplines
+    DeleteContent,
+    /// Make content public, otherwise writes stay drafts until reviewed
+    Publish,
+    /// Invite users, change their roles and manage API keys, lockouts and second factors
+    ManageUsers,
+    /// Read who changed what in the audit log
+    ViewAuditLog,
+    /// Wipe all content at once via deleteAllData
+    DeleteAllData,
+}
+
+impl Role {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Editor => matches!(
+                permission,
+                Permission::OwnAccount | Permission::ViewAdminData | Permission::EditContent | Permission::EditTaxonomy
+            ),
+            Role::Viewer => matches!(permission, Permission::OwnAccount | Permission::ViewAdminData),
+        }
+    }
+}
+
+/// Roles that can change or delete content need a second factor
+pub fn totp_required(role: &Role) -> bool {
+    matches!(role, Role::Admin | Role::Editor)
+}
+
+impl ApiKeyScope {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            ApiKeyScope::ReadOnly => permission == Permission::ViewAdminData,
+            ApiKeyScope::ContentWrite => matches!(
+                permission,
+                Permission::ViewAdminData | Permission::EditContent | Permission::EditTaxonomy
+            ),
+            // Imports replace existing records, so they may also delete them
+            ApiKeyScope::Import => matches!(
+                permission,
+                Permission::EditContent | Permission::EditTaxonomy | Permission::DeleteContent
+            ),
+        }
+    }
+}
+
+/// Caller of a procedure behind the auth middleware, either a signed in user or an API key
+#[derive(Debug, Clone)]
+pub enum Principal {
+    User(Claims),
+    ApiKey { id: String, name: String, scopes: Vec<ApiKeyScope> },
+}
+
+impl Principal {
+    /// API keys never get account or user management permissions, whatever scopes they carry
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            Principal::User(claims) => claims.role.has_permission(permission),
+            Principal::ApiKey { scopes, .. } => scopes.iter().any(|scope| scope.has_permission(permission)),
+        }
+    }
+
+    /// Recorded as the author of changes
+    pub fn actor(&self) -> String {
+        match self {
+            Principal::User(claims) => claims.email.clone(),
+            Principal::ApiKey { name, .. } => format!("apikey:{}", name),
+        }
+    }
+
+    pub fn claims(&self) -> Option<&Claims> {
+        match self {
+            Principal::User(claims) => Some(claims),
+            Principal::ApiKey { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    /// The stored hash is neither a PHC string nor a legacy `salt$hash` record
+    MalformedHash,
+    /// Argon2 could not hash the password
+    Hashing(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MalformedHash => write!(f, "Stored password hash is malformed"),
+            AuthError::Hashing(e) => write!(f, "Password hashing failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<AuthError> for rspc::Error {
+    fn from(error: AuthError) -> Self {
+        rspc::Error::new(ErrorCode::InternalServerError, error.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum BootstrapError {
+    Query(QueryError),
+    Auth(AuthError),
+}
+
+impl fmt::Display for BootstrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootstrapError::Query(e) => write!(f, "{}", e),
+            BootstrapError::Auth(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<QueryError> for BootstrapError {
+    fn from(error: QueryError) -> Self {
+        BootstrapError::Query(error)
+    }
+}
+
+impl From<AuthError> for BootstrapError {
+    fn from(error: AuthError) -> Self {
+        BootstrapError::Auth(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordMatch {
+    Valid,
+    /// Password is correct, but the record still uses the legacy SHA-256 scheme and should be rehashed
+    ValidLegacy,
+    Invalid,
 }
 
-/// Computes the base64 encoded digest of a salted password. Returns salt and hash
-pub fn gen_salt_hash(salt: &str, password: &str) -> String {
-    let salt_pw = format!["{}{}",salt, password];
-    let hash = digest::digest(&digest::SHA256, salt_pw.as_bytes());
-    format!["{}{}{}", salt, SALT_SEP, base64::encode(hash.as_ref())]
+/// Hashes a password with Argon2id and a random salt. Returns a PHC string
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::Hashing(e.to_string()))
 }
 
-/// Compares a password to any salthash
-pub fn cmp_salt_hash(password: &str, salt_hash: &str) -> bool {
-    let salt_hash_arr: Vec<&str> = salt_hash.split(SALT_SEP).collect();
-    let (db_salt, db_hash) = (salt_hash_arr.get(0).unwrap(), base64::decode(salt_hash_arr.get(1).unwrap()).unwrap()); //TODO: proper error handling
+/// Compares a password to a stored hash, accepting both PHC strings and legacy `salt$hash` records
+pub fn verify_password(password: &str, stored_hash: &str) -> Result<PasswordMatch, AuthError> {
+    if stored_hash.starts_with(PHC_ARGON2_PREFIX) {
+        let parsed = PasswordHash::new(stored_hash).map_err(|_| AuthError::MalformedHash)?;
+        if parsed.hash.is_none() {
+            return Err(AuthError::MalformedHash);
+        }
+        return match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(PasswordMatch::Valid),
+            Err(argon2::password_hash::Error::Password) => Ok(PasswordMatch::Invalid),
+            Err(_) => Err(AuthError::MalformedHash),
+        };
+    }
+    if cmp_legacy_salt_hash(password, stored_hash)? {
+        Ok(PasswordMatch::ValidLegacy)
+    } else {
+        Ok(PasswordMatch::Invalid)
+    }
+}
+
+/// Compares a password to a legacy `salt$base64(sha256(salt + password))` record
+fn cmp_legacy_salt_hash(password: &str, salt_hash: &str) -> Result<bool, AuthError> {
+    // The salt may itself contain the separator, base64 never does
+    let (db_salt, db_hash) = salt_hash
+        .rsplit_once(LEGACY_SALT_SEP)
+        .ok_or(AuthError::MalformedHash)?;
+    let db_hash = base64::decode(db_hash).map_err(|_| AuthError::MalformedHash)?;
+    if db_hash.len() != digest::SHA256_OUTPUT_LEN {
+        return Err(AuthError::MalformedHash);
+    }
     let salt_pw = format!["{}{}", db_salt, password];
     let gen_hash = digest::digest(&digest::SHA256, salt_pw.as_bytes());
-    gen_hash.as_ref().eq(&db_hash)
+    Ok(constant_time::verify_slices_are_equal(gen_hash.as_ref(), &db_hash).is_ok())
 }
 
-//src: https://blog.logrocket.com/jwt-authentication-in-rust/
-pub fn gen_jwt(email: &str, role: &Role) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::days(1))
-        .expect("valid timestamp")
-        .timestamp();
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// An environment variable could not be parsed
+    Invalid(&'static str, String),
+    /// The development placeholder secret is configured in a release build
+    PlaceholderSecret,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Invalid(var, reason) => write!(f, "Invalid {}: {}", var, reason),
+            ConfigError::PlaceholderSecret => write!(f, "Refusing to use the placeholder JWT secret in a release build, set JWT_KEYS"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+struct JwtKey {
+    kid: String,
+    secret: Vec<u8>,
+}
+
+/// Signing keys, algorithm and token lifetimes for session JWTs.
+/// The first key signs new tokens, every key is accepted when decoding so that keys can be rotated.
+pub struct JwtConfig {
+    keys: Vec<JwtKey>,
+    algorithm: Algorithm,
+    expiry: chrono::Duration,
+    refresh_expiry: chrono::Duration,
+    /// Origins whose requests may authenticate with the session cookie instead of the Authorization header
+    cookie_origins: Vec<String>,
+}
+
+// Manual impl so the secrets never end up in the request log
+impl fmt::Debug for JwtConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtConfig")
+            .field("kids", &self.keys.iter().map(|key| key.kid.as_str()).collect::<Vec<_>>())
+            .field("algorithm", &self.algorithm)
+            .field("expiry", &self.expiry)
+            .field("refresh_expiry", &self.refresh_expiry)
+            .field("cookie_origins", &self.cookie_origins)
+            .finish()
+    }
+}
+
+impl JwtConfig {
+    pub fn new(keys: Vec<(String, String)>, algorithm: Algorithm, expiry: chrono::Duration, refresh_expiry: chrono::Duration) -> Result<Self, ConfigError> {
+        if keys.is_empty() {
+            return Err(ConfigError::Invalid("JWT_KEYS", "at least one key is required".to_string()));
+        }
+        for (i, (kid, secret)) in keys.iter().enumerate() {
+            if kid.is_empty() || secret.is_empty() {
+                return Err(ConfigError::Invalid("JWT_KEYS", format!("empty kid or secret in entry {}", i + 1)));
+            }
+            if keys[..i].iter().any(|(other, _)| other == kid) {
+                return Err(ConfigError::Invalid("JWT_KEYS", format!("duplicate kid '{}'", kid)));
+            }
+        }
+        // Keys are plain shared secrets, so only the HMAC family makes sense
+        if !matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(ConfigError::Invalid("JWT_ALGORITHM", format!("{:?} is not an HMAC algorithm", algorithm)));
+        }
+        if expiry <= chrono::Duration::zero() {
+            return Err(ConfigError::Invalid("JWT_EXPIRY_MINUTES", "must be positive".to_string()));
+        }
+        if refresh_expiry <= expiry {
+            return Err(ConfigError::Invalid("JWT_REFRESH_DAYS", "must be longer than the access token lifetime".to_string()));
+        }
+        Ok(JwtConfig {
+            keys: keys.into_iter().map(|(kid, secret)| JwtKey { kid, secret: secret.into_bytes() }).collect(),
+            algorithm,
+            expiry,
+            refresh_expiry,
+            cookie_origins: vec![],
+        })
+    }
+
+    pub fn with_cookie_origins(self, cookie_origins: Vec<String>) -> Self {
+        JwtConfig { cookie_origins, ..self }
+    }
+
+    pub fn cookie_origins(&self) -> &[String] {
+        &self.cookie_origins
+    }
+
+    /// Reads `JWT_KEYS` (`kid:secret,kid:secret`, signing key first), `JWT_ALGORITHM`, `JWT_EXPIRY_MINUTES`, `JWT_REFRESH_DAYS`
+    /// and `SESSION_COOKIE_ORIGINS` (comma separated, the session cookie is not accepted without it).
+    /// Without `JWT_KEYS` the placeholder secret is used, which is only allowed in debug builds.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let keys = match env::var("JWT_KEYS") {
+            Ok(keys) => parse_jwt_keys(&keys)?,
+            Err(_) => vec![(PLACEHOLDER_JWT_KID.to_string(), PLACEHOLDER_JWT_SECRET.to_string())],
+        };
+        let algorithm = match env::var("JWT_ALGORITHM") {
+            Ok(algorithm) => Algorithm::from_str(&algorithm)
+                .map_err(|e| ConfigError::Invalid("JWT_ALGORITHM", e.to_string()))?,
+            Err(_) => Algorithm::HS512,
+        };
+        let expiry_minutes = match env::var("JWT_EXPIRY_MINUTES") {
+            Ok(minutes) => minutes.parse::<i64>()
+                .map_err(|e| ConfigError::Invalid("JWT_EXPIRY_MINUTES", e.to_string()))?,
+            Err(_) => DEFAULT_JWT_EXPIRY_MINUTES,
+        };
+        let refresh_days = match env::var("JWT_REFRESH_DAYS") {
+            Ok(days) => days.parse::<i64>()
+                .map_err(|e| ConfigError::Invalid("JWT_REFRESH_DAYS", e.to_string()))?,
+            Err(_) => DEFAULT_REFRESH_EXPIRY_DAYS,
+        };
+        let cookie_origins = env::var("SESSION_COOKIE_ORIGINS")
+            .map(|origins| origins.split(',').map(|origin| origin.trim().to_string()).filter(|origin| !origin.is_empty()).collect())
+            .unwrap_or_default();
+        let config = JwtConfig::new(keys, algorithm, chrono::Duration::minutes(expiry_minutes), chrono::Duration::days(refresh_days))?
+            .with_cookie_origins(cookie_origins);
+        if !cfg!(debug_assertions) && config.uses_placeholder_secret() {
+            return Err(ConfigError::PlaceholderSecret);
+        }
+        Ok(config)
+    }
+
+    pub fn uses_placeholder_secret(&self) -> bool {
+        self.keys.iter().any(|key| key.secret == PLACEHOLDER_JWT_SECRET.as_bytes())
+    }
 
+    pub fn refresh_expiry(&self) -> chrono::Duration {
+        self.refresh_expiry
+    }
+
+    fn signing_key(&self) -> &JwtKey {
+        &self.keys[0]
+    }
+
+    /// Tokens without a kid predate key rotation and were signed with the signing key
+    fn decoding_key(&self, kid: Option<&str>) -> Option<&JwtKey> {
+        match kid {
+            Some(kid) => self.keys.iter().find(|key| key.kid == kid),
+            None => Some(self.signing_key()),
+        }
+    }
+}
+
+fn parse_jwt_keys(keys: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .enumerate()
+        .map(|(i, entry)| {
+            // Don't echo the entry, it may be a secret
+            entry.split_once(':')
+                .map(|(kid, secret)| (kid.trim().to_string(), secret.to_string()))
+                .ok_or_else(|| ConfigError::Invalid("JWT_KEYS", format!("entry {} is not of the form kid:secret", i + 1)))
+        })
+        .collect()
+}
+
+//src: https://blog.logrocket.com/jwt-authentication-in-rust/
+pub fn gen_jwt(config: &JwtConfig, email: &str, role: &Role, session_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
     let claims = Claims {
         email: email.to_string(),
         role: role.clone(),
-        exp: expiration as usize,
+        sid: session_id.to_string(),
+        exp: expiration(config.expiry),
+    };
+    encode_jwt(config, &claims)
+}
+
+pub fn decode_jwt(config: &JwtConfig, jwt: &str) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
+    decode_signed(config, jwt)
+}
+
+pub fn gen_mfa_jwt(config: &JwtConfig, email: &str) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = MfaClaims {
+        mfa_email: email.to_string(),
+        exp: expiration(chrono::Duration::minutes(MFA_TOKEN_MINUTES)),
     };
-    let header = Header::new(jsonwebtoken::Algorithm::HS512);
-    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(JWT_SECRET))
-        //.map_err(|_| jsonwebtoken::errors::Error::JWTTokenCreationError)
+    encode_jwt(config, &claims)
+}
+
+pub fn decode_mfa_jwt(config: &JwtConfig, jwt: &str) -> Result<TokenData<MfaClaims>, jsonwebtoken::errors::Error> {
+    decode_signed(config, jwt)
+}
+
+fn expiration(valid_for: chrono::Duration) -> usize {
+    Utc::now()
+        .checked_add_signed(valid_for)
+        .expect("valid timestamp")
+        .timestamp() as usize
+}
+
+fn encode_jwt<T: Serialize>(config: &JwtConfig, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+    let key = config.signing_key();
+    let mut header = Header::new(config.algorithm);
+    header.kid = Some(key.kid.clone());
+    jsonwebtoken::encode(&header, claims, &EncodingKey::from_secret(&key.secret))
+}
+
+fn decode_signed<T: DeserializeOwned>(config: &JwtConfig, jwt: &str) -> Result<TokenData<T>, jsonwebtoken::errors::Error> {
+    let header = jsonwebtoken::decode_header(jwt)?;
+    let key = config.decoding_key(header.kid.as_deref()).ok_or(ErrorKind::InvalidToken)?;
+    jsonwebtoken::decode::<T>(jwt, &DecodingKey::from_secret(&key.secret), &jsonwebtoken::Validation::new(config.algorithm))
+}
+
+/// Creates the first admin account, promoting the user if the email is already registered.
+/// Does nothing and returns false once any admin exists, so it is safe to run on every startup
+pub async fn bootstrap_admin(db: &PrismaClient, email: &str, password: &str) -> Result<bool, BootstrapError> {
+    let admins = db
+        .users()
+        .count(vec![users::role::equals(Role::Admin)])
+        .exec()
+        .await?;
+    if admins > 0 {
+        return Ok(false);
+    }
+    let hash = hash_password(password)?;
+    db
+        .users()
+        .upsert(
+            users::email::equals(email.to_string()),
+            users::create(email.to_string(), hash.clone(), Role::Admin, vec![]),
+            vec![users::hash::set(hash), users::role::set(Role::Admin)]
+        )
+        .exec()
+        .await?;
+    Ok(true)
+}
+
+/// Generates an opaque random token (refresh tokens, invite codes). Only its hash is stored
+pub fn gen_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system randomness available");
+    base64::encode_engine(bytes, &URL_SAFE_NO_PAD)
 }
 
-pub fn decode_jwt(jwt: &str) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
-    jsonwebtoken::decode::<Claims>(jwt, &DecodingKey::from_secret(JWT_SECRET), &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS512))
+/// Generates a new API key. The prefix tells it apart from session tokens, which are JWTs
+pub fn gen_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, gen_token())
 }
 
-pub fn check_admin(jwt: &str) -> bool {
-    if let Ok(decoded) = decode_jwt(jwt) {
-        return decoded.claims.role == Role::Admin 
-            && decoded.claims.exp > Utc::now().timestamp() as usize;
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// Start of an API key, stored in plain so admins can recognize their keys
+pub fn api_key_display_prefix(key: &str) -> String {
+    key.chars().take(API_KEY_DISPLAY_CHARS).collect()
+}
+
+/// Tokens from gen_token are high entropy, so a plain digest is enough to keep them unusable if the table leaks
+pub fn hash_token(token: &str) -> String {
+    base64::encode(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+/// Reads the session token from an `Authorization: Bearer` header or, failing that, the HttpOnly session cookie.
+/// Procedures are plain GET queries, so besides SameSite=Strict the cookie only counts for requests whose
+/// `Origin` is one of `cookie_origins`. Cross-site forms and links don't send a matching one
+pub fn session_token<'a>(headers: &'a HeaderMap, cookie_origins: &[String]) -> Option<&'a str> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(BEARER_PREFIX));
+    if bearer.is_some() {
+        return bearer;
+    }
+    let origin = headers.get(ORIGIN).and_then(|value| value.to_str().ok())?;
+    if !cookie_origins.iter().any(|allowed| allowed == origin) {
+        return None;
+    }
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_SESSION_KEY)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// `Set-Cookie` value holding an access token for `max_age`, an empty token with zero age removes the cookie
+pub fn session_cookie(token: &str, max_age: Duration) -> String {
+    format!(
+        "{}={}; HttpOnly; Secure; SameSite=Strict; Path=/api; Max-Age={}",
+        COOKIE_SESSION_KEY, token, max_age.num_seconds().max(0)
+    )
+}
+
+/// How many failed logins a throttle key (account or client ip) gets before it is locked out
+#[derive(Debug)]
+pub struct LoginLimit {
+    /// Failures allowed without any delay
+    pub free_attempts: i32,
+    /// Failures older than this are forgotten
+    pub window_seconds: i64,
+}
+
+pub const ACCOUNT_LOGIN_LIMIT: LoginLimit = LoginLimit { free_attempts: 5, window_seconds: 60 * 60 };
+// One ip may be shared by many legitimate users (office, NAT), so it gets more slack
+pub const IP_LOGIN_LIMIT: LoginLimit = LoginLimit { free_attempts: 20, window_seconds: 60 * 60 };
+
+/// Lockout after the given number of consecutive failures. Doubles per failure over the limit, capped at one hour
+pub fn lockout_duration(limit: &LoginLimit, failures: i32) -> Option<Duration> {
+    if failures <= limit.free_attempts {
+        return None;
     }
-    false
+    let exponent = (failures - limit.free_attempts - 1).min(16) as u32;
+    let seconds = LOCKOUT_BASE_SECONDS.saturating_mul(1 << exponent).min(LOCKOUT_MAX_SECONDS);
+    Some(Duration::seconds(seconds))
 }
@@ -0,0 +1,30 @@
+use std::{env, io::{self, Write}};
+
+use bidt_glossar_server::{auth::bootstrap_admin, db};
+
+// Creates the first admin account: cargo createadmin <email>
+// The password is read from ADMIN_PASSWORD or prompted for, so it does not end up in the shell history
+#[tokio::main]
+async fn main() {
+    let email = env::args()
+        .nth(1)
+        .or_else(|| env::var("ADMIN_EMAIL").ok())
+        .expect("Usage: cargo createadmin <email> (or set ADMIN_EMAIL)");
+    let password = env::var("ADMIN_PASSWORD").unwrap_or_else(|_| {
+        print!("Password for {}: ", email);
+        io::stdout().flush().unwrap();
+        let mut password = String::new();
+        io::stdin().read_line(&mut password).expect("Password could not be read");
+        password.trim_end_matches(&['\r', '\n'][..]).to_string()
+    });
+    if password.is_empty() {
+        panic!("Password must not be empty");
+    }
+
+    let prisma_client = db::new_client().await.unwrap();
+    match bootstrap_admin(&prisma_client, &email, &password).await {
+        Ok(true) => println!("Created admin {}", email),
+        Ok(false) => println!("An admin already exists, invite further users from the admin panel"),
+        Err(e) => panic!("Creating admin {} failed: {}", email, e),
+    }
+}
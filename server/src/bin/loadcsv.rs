@@ -1,5 +1,5 @@
 use serde::Deserialize;
-use bidt_glossar_server::db::{self, article, category, discipline, edge, node};
+use bidt_glossar_server::{alias::{normalize_alias, resolve_node_name}, db::{self, article, author, category, discipline, edge, node, node_alias, related_link}, graph::{canonical_edge_key, merge_reverse_edges, resolve_edge_key}, import::{parse_aliases, parse_assessment, parse_authors, parse_csv_date, parse_enabler, parse_read_time, parse_related_links, CsvLink, CsvValueError}};
 
 //IMPORTANT: this is a relative path from where you run "cargo loadcsv" from, default is from /server/
 const NODE_PATH: &str = "./data/nodes.csv";
@@ -16,7 +16,9 @@ struct NodeCSVRecord {
     RelatedWebContent: String,
     Erstellungsdatum: String,
     AnzahlAbrufe: String,
-    ErwLesedauer: String
+    ErwLesedauer: String,
+    #[serde(default)] // optional column, older exports don't have it
+    Synonyme: String
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,16 +40,98 @@ struct EdgeCSVRecord {
     ErwLesedauer: String
 }
 
+/// Unparseable cells are reported at the end, the row is imported without the value
+fn or_report<T: Default>(parsed: Result<T, CsvValueError>, row: &str, value_errors: &mut Vec<String>) -> T {
+    parsed.unwrap_or_else(|e| {
+        value_errors.push(format!("{}: {}", row, e));
+        T::default()
+    })
+}
+
+/// Aliases follow the rules of modNodeAlias: names take precedence and an alias stays with the node it was first given to.
+/// Returns false for an alias that is just the node's own name
+async fn check_alias(prisma_client: &db::PrismaClient, node_name: &str, alias: &str) -> Result<bool, CsvValueError> {
+    let owner = resolve_node_name(prisma_client, alias)
+        .await
+        .expect(format!("Resolving alias {} failed", alias).as_str());
+    match owner {
+        Some(owner) if owner != node_name => Err(CsvValueError {
+            column: "Synonyme",
+            value: alias.to_string(),
+            reason: Some(format!("already refers to '{}'", owner)),
+        }),
+        _ => Ok(alias != node_name),
+    }
+}
+
+/// Links are only imported for nodes and articles without any, so lists edited through the API survive a reload
+async fn import_links(prisma_client: &db::PrismaClient, owner_filter: related_link::WhereParam, owner: impl Fn() -> related_link::SetParam, links: Vec<CsvLink>) {
+    let existing = prisma_client
+        .related_link()
+        .count(vec![owner_filter])
+        .exec()
+        .await
+        .expect("Counting related links failed");
+    if existing > 0 {
+        return;
+    }
+    for (position, link) in links.into_iter().enumerate() {
+        prisma_client
+            .related_link()
+            .create(
+                link.url.clone(),
+                vec![
+                    related_link::title::set(link.title),
+                    related_link::kind::set(link.kind),
+                    related_link::position::set(position as i32),
+                    owner(),
+                ],
+            )
+            .exec()
+            .await
+            .expect(format!("Create failed on link {}", link.url).as_str());
+    }
+}
+
+/// Authors are matched by name. The discipline is only set for new authors, profiles edited through the API stay as they are
+async fn import_authors(prisma_client: &db::PrismaClient, names: &[String], discipline: Option<&str>) {
+    for name in names {
+        let profile = discipline
+            .map(|discipline| author::discipline::connect(discipline::name::equals(discipline.to_string())))
+            .into_iter()
+            .collect();
+        prisma_client
+            .author()
+            .upsert(
+                author::name::equals(name.clone()),
+                author::create(name.clone(), profile),
+                vec![],
+            )
+            .exec()
+            .await
+            .expect(format!("Upsert failed on author {}", name).as_str());
+    }
+}
+
+fn author_refs(names: &[String]) -> Vec<author::UniqueWhereParam> {
+    names.iter().map(|name| author::name::equals(name.clone())).collect()
+}
+
 #[tokio::main]
 async fn main() {
     let prisma_client = db::new_client().await.unwrap();
+    let mut value_errors = vec![];
+    let mut node_aliases = vec![];
 
     // Read node related data
     let mut node_rdr = csv::ReaderBuilder::new().delimiter(b';').from_path(NODE_PATH)
         .expect(format!("Node path {} could not be found", NODE_PATH).as_str());
-    for result in node_rdr.deserialize() {
+    for (index, result) in node_rdr.deserialize().enumerate() {
         let record: NodeCSVRecord = result.unwrap();
         println!("{:?}", record);
+        let row = format!("{} row {} '{}'", NODE_PATH, index + 2, record.name); // +2: header and 1-based rows
+        let read_time = or_report(parse_read_time(&record.ErwLesedauer), &row, &mut value_errors);
+        let links = or_report(parse_related_links(&record.RelatedWebContent), &row, &mut value_errors);
 
         //upsert category
         prisma_client
@@ -61,7 +145,24 @@ async fn main() {
             .await
             .expect(format!("Upsert failed on {}", record.gruppe.clone()).as_str());
 
-        //upsert node entries
+        //upsert authors, their discipline has to exist first
+        let authors = parse_authors(&record.Autor);
+        let author_discipline = record.Autorendisziplin.trim();
+        if !author_discipline.is_empty() {
+            prisma_client
+                .discipline()
+                .upsert(
+                    discipline::name::equals(author_discipline.to_string()),
+                    discipline::create(author_discipline.to_string(), vec![]),
+                    vec![],
+                )
+                .exec()
+                .await
+                .expect(format!("Upsert failed on {}", author_discipline).as_str());
+        }
+        import_authors(&prisma_client, &authors, (!author_discipline.is_empty()).then_some(author_discipline)).await;
+
+        //upsert node entries, authors are added on reloads as well
         prisma_client
             .node()
             .upsert(
@@ -73,15 +174,47 @@ async fn main() {
                     vec![
                         node::teaser_text::set(record.TeaserText),
                         node::views::set(record.AnzahlAbrufe.parse::<i32>().unwrap_or_default()),
-                        node::read_time::set(record.ErwLesedauer),
-                        node::author::set(record.Autor)
+                        node::read_time_minutes::set(read_time),
+                        node::authors::connect(author_refs(&authors)),
                     ],
                 ),
-                vec![],
+                // Reloads fill the columns of rows that were imported before they existed
+                vec![
+                    node::read_time_minutes::set(read_time),
+                    node::authors::connect(author_refs(&authors)),
+                ],
             )
             .exec()
             .await
             .expect(format!("Update failed on {}", record.gruppe.clone()).as_str());
+
+        import_links(
+            &prisma_client,
+            related_link::node_name::equals(Some(record.name.clone())),
+            || related_link::node::connect(node::name::equals(record.name.clone())),
+            links,
+        ).await;
+
+        node_aliases.push((row, record.name, parse_aliases(&record.Synonyme)));
+    }
+
+    // Aliases once all nodes exist, so they are checked against the names further down the file as well
+    for (row, node_name, aliases) in node_aliases {
+        for alias in aliases {
+            if !or_report(check_alias(&prisma_client, &node_name, &alias).await, &row, &mut value_errors) {
+                continue;
+            }
+            prisma_client
+                .node_alias()
+                .upsert(
+                    node_alias::normalized::equals(normalize_alias(&alias)),
+                    node_alias::create(normalize_alias(&alias), alias.clone(), node::name::equals(node_name.clone()), vec![]),
+                    vec![],
+                )
+                .exec()
+                .await
+                .expect(format!("Upsert failed on alias {}", alias).as_str());
+        }
     }
 
     // Read edge related data
@@ -90,20 +223,32 @@ async fn main() {
     for (index, result) in edge_rdr.deserialize().enumerate() {
         let record: EdgeCSVRecord = result.unwrap();
         println!("{:?}", record);
+        let row = format!("{} row {} '{}'", EDGE_PATH, index + 2, record.Kantentitel);
+        let date = or_report(parse_csv_date(&record.Erstellungsdatum), &row, &mut value_errors);
+        let read_time = or_report(parse_read_time(&record.ErwLesedauer), &row, &mut value_errors);
+        let digital_specificity = or_report(parse_assessment("Digitalspezifität", &record.Digitalspezifität), &row, &mut value_errors);
+        let analog_comparison = or_report(parse_assessment("VerglAnalogePhänomene", &record.VerglAnalogePhänomene), &row, &mut value_errors);
+        let societal_relevance = or_report(parse_assessment("GesellschaftlRelevanz", &record.GesellschaftlRelevanz), &row, &mut value_errors);
+        let enabler = or_report(parse_enabler(&record.Enabler), &row, &mut value_errors);
+        let links = or_report(parse_related_links(&record.RelatedWebContent), &row, &mut value_errors);
 
-        //upsert edge
+        //upsert edge, the CSV edges are undirected so a reverse row reuses the existing edge
+        let (source_id, target_id) = resolve_edge_key(&prisma_client, &record.Knoten1, &record.Knoten2, false)
+            .await
+            .expect(format!("Lookup failed on {} {}", record.Knoten1, record.Knoten2).as_str())
+            .unwrap_or_else(|| canonical_edge_key(record.Knoten1.clone(), record.Knoten2.clone(), false));
         prisma_client
             .edge()
             .upsert(
-                edge::source_id_target_id(record.Knoten1.clone(), record.Knoten2.clone()),
+                edge::source_id_target_id(source_id.clone(), target_id.clone()),
                 edge::create(
-                    node::name::equals(record.Knoten1.clone()),
-                    node::name::equals(record.Knoten2.clone()),
+                    node::name::equals(source_id.clone()),
+                    node::name::equals(target_id.clone()),
                     vec![
                         // TODO: Set all the "needed" fields in here
                     ],
                 ),
-                vec![], //TODO: maybe delete edges if the other way around exists?
+                vec![],
             )
             .exec()
             .await
@@ -128,7 +273,10 @@ async fn main() {
         .await
         .expect(format!("Upsert failed on {}", record.Autorendisziplin.clone()).as_str());
         
-        //upsert article for edge 
+        let authors = parse_authors(&record.Autor);
+        import_authors(&prisma_client, &authors, Some(&record.Autorendisziplin)).await;
+
+        //upsert article for edge, authors are added on reloads as well
         prisma_client
             .article()
             .upsert(
@@ -136,28 +284,62 @@ async fn main() {
                 article::name::equals(record.Kantentitel.clone()),
                 article::create(
                     record.Kantentitel.clone(),
-                    edge::source_id_target_id(record.Knoten1.clone(), record.Knoten2.clone()),
+                    edge::source_id_target_id(source_id, target_id),
                     discipline::name::equals(record.Autorendisziplin.clone()),
                     vec![
-                        article::author::set(record.Autor),
                         article::teaser_text::set(record.TeaserText),
-                        article::date::set(record.Erstellungsdatum),
+                        article::date::set(date),
                         article::url::set(record.URL),
                         article::views::set(record.AnzahlAbrufe.parse::<i32>().unwrap_or_default()),
-                        article::read_time::set(record.ErwLesedauer)
+                        article::read_time_minutes::set(read_time),
+                        article::digital_specificity::set(digital_specificity),
+                        article::analog_comparison::set(analog_comparison),
+                        article::societal_relevance::set(societal_relevance),
+                        article::enabler::set(enabler),
+                        article::authors::connect(author_refs(&authors)),
                     ],
                     /*
-                    author     String @default("Unknown")
                     teaserText String @default("Unknown")
-                    date       String @default("1/1/2022") //TODO make this a real date if the data allow it
+                    date       DateTime?
                     url        String @default("https://www.bidt.digital/glossar/nudging/")
                     views      Int    @default(0)
                      */
                 ),
-                vec![],
+                // Reloads fill the columns of rows that were imported before they existed
+                vec![
+                    article::date::set(date),
+                    article::read_time_minutes::set(read_time),
+                    article::digital_specificity::set(digital_specificity),
+                    article::analog_comparison::set(analog_comparison),
+                    article::societal_relevance::set(societal_relevance),
+                    article::enabler::set(enabler),
+                    article::authors::connect(author_refs(&authors)),
+                ],
             )
             .exec()
             .await
             .expect(format!("Update failed on {}", record.Kantentitel.clone()).as_str());
+
+        import_links(
+            &prisma_client,
+            related_link::article_name::equals(Some(record.Kantentitel.clone())),
+            || related_link::article::connect(article::name::equals(record.Kantentitel.clone())),
+            links,
+        ).await;
+    }
+
+    // Databases loaded before edges had a direction may still hold both orders
+    let merged = merge_reverse_edges(&prisma_client)
+        .await
+        .expect("Merging reverse edges failed");
+    if merged > 0 {
+        println!("Moved {} undirected edges to their canonical order", merged);
+    }
+
+    if !value_errors.is_empty() {
+        println!("{} values could not be imported and were left empty:", value_errors.len());
+        for value_error in value_errors {
+            println!("  {}", value_error);
+        }
     }
 }
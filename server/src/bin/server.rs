@@ -1,32 +1,67 @@
-use axum::{Router};
-use bidt_glossar_server::{db, routes};
-use std::sync::Arc;
+use axum::{http::{header::{AUTHORIZATION, CONTENT_TYPE}, HeaderValue, Method}, Router};
+use bidt_glossar_server::{auth::{bootstrap_admin, JwtConfig}, db, mail::mailer_from_env, routes, schedule};
+use std::{env, net::SocketAddr, sync::Arc};
 use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 #[tokio::main]
 async fn main() {
+    // session token signing keys, refuses the placeholder secret in release builds
+    let jwt_config = Arc::new(JwtConfig::from_env().unwrap_or_else(|e| panic!("JWT configuration: {}", e)));
+    if jwt_config.uses_placeholder_secret() {
+        println!("WARNING: using the placeholder JWT secret, set JWT_KEYS before deploying");
+    }
+
+    // outgoing mail (password resets), SMTP or a local file/stdout sink
+    let mailer = mailer_from_env().unwrap_or_else(|e| panic!("Mail configuration: {}", e));
+
     // database connector
     let prisma_client = Arc::new(Mutex::new(db::new_client().await.unwrap()));
 
     #[cfg(debug)]
     prisma_client._db_push(false).await.unwrap();
 
+    // first admin for fresh deployments, later users are invited
+    if let (Ok(email), Ok(password)) = (env::var("ADMIN_EMAIL"), env::var("ADMIN_PASSWORD")) {
+        match bootstrap_admin(&*prisma_client.lock().await, &email, &password).await {
+            Ok(true) => println!("Created admin {}", email),
+            Ok(false) => {}
+            Err(e) => panic!("Creating admin {} failed: {}", email, e),
+        }
+    }
+
+    // flips scheduled content to published and expired content back to draft
+    tokio::spawn(schedule::run(prisma_client.clone()));
+
     // TODO: specify for production
-    let cors = CorsLayer::new()
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .allow_origin(Any);
+    // The session cookie needs credentialed requests, which browsers refuse with wildcard CORS rules
+    let cors = if jwt_config.cookie_origins().is_empty() {
+        CorsLayer::new()
+            .allow_methods(Any)
+            .allow_headers(Any)
+            .allow_origin(Any)
+    } else {
+        let origins = jwt_config
+            .cookie_origins()
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin).unwrap_or_else(|e| panic!("SESSION_COOKIE_ORIGINS: {}", e)))
+            .collect::<Vec<_>>();
+        CorsLayer::new()
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
+            .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_credentials(true)
+    };
 
     let app = Router::new()
-        .nest("/api", routes::create_route(prisma_client).await)
+        .nest("/api", routes::create_route(prisma_client, jwt_config, mailer).await)
         .layer(cors);
     //.layer(Extension(prisma_client));
 
     println!("Example Prisma x Axum running on http://localhost:5000/api");
 
     axum::Server::bind(&"0.0.0.0:5000".parse().unwrap())
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>()) // client ip for login throttling
         .await
         .unwrap();
 }
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use prisma_client_rust::QueryError;
+
+use crate::db::{article, edge, PrismaClient};
+
+/// Key an edge is stored under. Undirected edges are ordered by node name, so A–B and B–A are the same row.
+/// The order is the byte order of the names, SQL that reorders edges has to compare with `COLLATE "C"` to agree with it
+pub fn canonical_edge_key(source_id: String, target_id: String, directed: bool) -> (String, String) {
+    if directed || source_id <= target_id {
+        (source_id, target_id)
+    } else {
+        (target_id, source_id)
+    }
+}
+
+/// Key an edge is stored under once one of its nodes is renamed. Undirected edges are reordered if the new name changes their order
+pub fn renamed_edge_key(source_id: &str, target_id: &str, directed: bool, old_name: &str, new_name: &str) -> (String, String) {
+    let rename = |name: &str| if name == old_name { new_name.to_string() } else { name.to_string() };
+    canonical_edge_key(rename(source_id), rename(target_id), directed)
+}
+
+/// Key two edges of a node would share after renaming it, given as (source, target, directed).
+/// Happens where a directed edge and an undirected one connect the same nodes and the undirected one gets reordered
+pub fn rename_edge_conflict(edges: &[(String, String, bool)], old_name: &str, new_name: &str) -> Option<(String, String)> {
+    let mut keys = HashSet::new();
+    edges
+        .iter()
+        .map(|(source_id, target_id, directed)| renamed_edge_key(source_id, target_id, *directed, old_name, new_name))
+        .find(|key| !keys.insert(key.clone()))
+}
+
+/// Key of the existing edge between two nodes: the edge as given, or its reverse if one of both is undirected
+pub async fn resolve_edge_key(db: &PrismaClient, source_id: &str, target_id: &str, directed: bool) -> Result<Option<(String, String)>, QueryError> {
+    let exact = db
+        .edge()
+        .find_unique(edge::source_id_target_id(source_id.to_string(), target_id.to_string()))
+        .exec()
+        .await?;
+    if let Some(exact) = exact {
+        return Ok(Some((exact.source_id, exact.target_id)));
+    }
+    let reverse = db
+        .edge()
+        .find_unique(edge::source_id_target_id(target_id.to_string(), source_id.to_string()))
+        .exec()
+        .await?;
+    Ok(reverse
+        .filter(|reverse| !directed || !reverse.directed)
+        .map(|reverse| (reverse.source_id, reverse.target_id)))
+}
+
+/// Moves undirected edges stored in the wrong order to their canonical key.
+/// If both orders exist, the articles of the reversed one are attached to the canonical edge and the duplicate is deleted
+pub async fn merge_reverse_edges(db: &PrismaClient) -> Result<usize, QueryError> {
+    let reversed = db
+        .edge()
+        .find_many(vec![edge::directed::equals(false)])
+        .exec()
+        .await?
+        .into_iter()
+        .filter(|edge| edge.source_id > edge.target_id)
+        .collect::<Vec<_>>();
+    for reversed_edge in &reversed {
+        let (source_id, target_id) = canonical_edge_key(reversed_edge.source_id.clone(), reversed_edge.target_id.clone(), false);
+        let canonical = db
+            .edge()
+            .find_unique(edge::source_id_target_id(source_id.clone(), target_id.clone()))
+            .exec()
+            .await?;
+        if canonical.is_some() {
+            db
+                .article()
+                .update_many(
+                    vec![
+                        article::edge_source_id::equals(reversed_edge.source_id.clone()),
+                        article::edge_target_id::equals(reversed_edge.target_id.clone()),
+                    ],
+                    vec![
+                        article::edge_source_id::set(source_id),
+                        article::edge_target_id::set(target_id),
+                    ]
+                )
+                .exec()
+                .await?;
+            db
+                .edge()
+                .delete(edge::source_id_target_id(reversed_edge.source_id.clone(), reversed_edge.target_id.clone()))
+                .exec()
+                .await?;
+        } else {
+            // Articles follow the new key, the relation cascades on update
+            db
+                .edge()
+                .update(
+                    edge::source_id_target_id(reversed_edge.source_id.clone(), reversed_edge.target_id.clone()),
+                    vec![edge::source_id::set(source_id), edge::target_id::set(target_id)]
+                )
+                .exec()
+                .await?;
+        }
+    }
+    Ok(reversed.len())
+}
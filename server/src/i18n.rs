@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+
+use crate::db::Locale;
+
+/// Locale of the original content, used whenever a translation is missing
+pub const FALLBACK_LOCALE: Locale = Locale::De;
+
+/// Picks per key the translation in the requested locale, falling back to German.
+/// Keys without either are missing from the result, callers then show the stable key itself
+pub fn pick_translations<T>(rows: impl IntoIterator<Item = (String, Locale, T)>, locale: Locale) -> HashMap<String, T> {
+    let mut picked = HashMap::new();
+    for (key, row_locale, value) in rows {
+        if row_locale == locale {
+            picked.insert(key, value);
+        } else if row_locale == FALLBACK_LOCALE {
+            picked.entry(key).or_insert(value);
+        }
+    }
+    picked
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use prisma_client_rust::chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+
+use crate::{alias::normalize_alias, db::{AssessmentLevel, LinkKind}};
+
+/// Date formats found in the CSV exports: German, US and ISO
+const CSV_DATE_FORMATS: [&str; 3] = ["%d.%m.%Y", "%m/%d/%Y", "%Y-%m-%d"];
+
+/// Prefixes for approximate read times, as in "ca. 5 Minuten"
+const READ_TIME_PREFIXES: [&str; 3] = ["ca.", "ca", "etwa"];
+/// Spellings of the assessment levels, the editors used German words and 1-3 scores
+const ASSESSMENT_LEVELS: [(&str, AssessmentLevel); 10] = [
+    ("niedrig", AssessmentLevel::Low),
+    ("gering", AssessmentLevel::Low),
+    ("low", AssessmentLevel::Low),
+    ("1", AssessmentLevel::Low),
+    ("mittel", AssessmentLevel::Medium),
+    ("medium", AssessmentLevel::Medium),
+    ("2", AssessmentLevel::Medium),
+    ("hoch", AssessmentLevel::High),
+    ("high", AssessmentLevel::High),
+    ("3", AssessmentLevel::High),
+];
+const YES: [&str; 5] = ["ja", "yes", "x", "1", "true"];
+const NO: [&str; 4] = ["nein", "no", "0", "false"];
+const URL_SCHEMES: [&str; 2] = ["https://", "http://"];
+/// Characters that end a URL in the link lists, besides whitespace
+const URL_TERMINATORS: [char; 5] = [',', ';', '|', ')', ']'];
+/// Characters around the titles, as in "Title: https://..", "Title - https://.." or "[Title](https://..)"
+const TITLE_SEPARATORS: [char; 11] = [',', ';', '|', ':', '-', '–', '(', ')', '[', ']', '"'];
+/// Separators of the `Synonyme` lists
+const ALIAS_SEPARATORS: [char; 4] = [',', ';', '|', '\n'];
+/// Separators of co-authors in the `Autor` cells
+const AUTHOR_SEPARATORS: [char; 5] = [',', ';', '&', '/', '\n'];
+const AUTHOR_WORD_SEPARATORS: [&str; 2] = [" und ", " and "];
+const VIDEO_HOSTS: [&str; 3] = ["youtube.com", "youtu.be", "vimeo.com"];
+/// Placeholder the CSV exports use for a missing value
+const UNKNOWN: &str = "unknown";
+
+/// A CSV cell that could not be parsed or clashes with existing data, the import goes on without the value and reports it
+#[derive(Debug, PartialEq, Eq)]
+pub struct CsvValueError {
+    pub column: &'static str,
+    pub value: String,
+    /// Why a parseable value was rejected, none for unrecognized values
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for CsvValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{} '{}' {}", self.column, self.value, reason),
+            None => write!(f, "Unrecognized {} '{}'", self.column, self.value),
+        }
+    }
+}
+
+impl std::error::Error for CsvValueError {}
+
+/// Parses an `Erstellungsdatum` cell. Empty cells are no date, days are stored as midnight UTC
+pub fn parse_csv_date(value: &str) -> Result<Option<DateTime<FixedOffset>>, CsvValueError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(Some(date_time));
+    }
+    CSV_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .map(|date| Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight exists")).into()))
+        .ok_or_else(|| CsvValueError { column: "Erstellungsdatum", value: value.to_string(), reason: None })
+}
+
+/// Parses an `ErwLesedauer` cell into minutes, e.g. "5", "5 Min." or "ca. 5 Minuten".
+/// Empty cells and "Unknown" are no read time
+pub fn parse_read_time(value: &str) -> Result<Option<i32>, CsvValueError> {
+    let error = || CsvValueError { column: "ErwLesedauer", value: value.to_string(), reason: None };
+    let normalized = value.trim().to_lowercase();
+    if normalized.is_empty() || normalized == UNKNOWN {
+        return Ok(None);
+    }
+    let rest = READ_TIME_PREFIXES
+        .iter()
+        .find_map(|prefix| normalized.strip_prefix(prefix))
+        .unwrap_or(&normalized)
+        .trim_start();
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let unit = rest[digits..].trim();
+    if !(unit.is_empty() || unit.starts_with("min")) {
+        return Err(error());
+    }
+    rest[..digits].parse().map(Some).map_err(|_| error())
+}
+
+/// Parses one of the assessment cells (`Digitalspezifität`, `VerglAnalogePhänomene`, `GesellschaftlRelevanz`)
+pub fn parse_assessment(column: &'static str, value: &str) -> Result<Option<AssessmentLevel>, CsvValueError> {
+    let normalized = value.trim().to_lowercase();
+    if normalized.is_empty() || normalized == UNKNOWN {
+        return Ok(None);
+    }
+    ASSESSMENT_LEVELS
+        .iter()
+        .find(|(spelling, _)| *spelling == normalized)
+        .map(|(_, level)| Some(*level))
+        .ok_or_else(|| CsvValueError { column, value: value.to_string(), reason: None })
+}
+
+/// Parses the `Enabler` cell, a yes/no mark
+pub fn parse_enabler(value: &str) -> Result<Option<bool>, CsvValueError> {
+    let normalized = value.trim().to_lowercase();
+    if normalized.is_empty() || normalized == UNKNOWN {
+        Ok(None)
+    } else if YES.contains(&normalized.as_str()) {
+        Ok(Some(true))
+    } else if NO.contains(&normalized.as_str()) {
+        Ok(Some(false))
+    } else {
+        Err(CsvValueError { column: "Enabler", value: value.to_string(), reason: None })
+    }
+}
+
+/// A link of a `RelatedWebContent` cell
+#[derive(Debug, PartialEq, Eq)]
+pub struct CsvLink {
+    pub url: String,
+    pub title: Option<String>,
+    pub kind: LinkKind,
+}
+
+/// Parses a `RelatedWebContent` cell, a list of URLs each optionally preceded by its title.
+/// Any separator between the entries works, as the URLs themselves mark where an entry starts
+pub fn parse_related_links(value: &str) -> Result<Vec<CsvLink>, CsvValueError> {
+    let mut links = vec![];
+    let mut rest = value;
+    while let Some(start) = URL_SCHEMES.iter().filter_map(|scheme| rest.find(scheme)).min() {
+        let title = rest[..start].trim_matches(|c: char| c.is_whitespace() || TITLE_SEPARATORS.contains(&c));
+        let end = rest[start..]
+            .find(|c: char| c.is_whitespace() || URL_TERMINATORS.contains(&c))
+            .map_or(rest.len(), |length| start + length);
+        let url = rest[start..end].trim_end_matches(['.', ':']);
+        links.push(CsvLink {
+            url: url.to_string(),
+            title: (!title.is_empty()).then(|| title.to_string()),
+            kind: link_kind(url),
+        });
+        rest = &rest[end..];
+    }
+    let normalized = value.trim().to_lowercase();
+    if links.is_empty() && !normalized.is_empty() && normalized != UNKNOWN {
+        return Err(CsvValueError { column: "RelatedWebContent", value: value.to_string(), reason: None });
+    }
+    Ok(links)
+}
+
+/// Kind of a link, guessed from its URL
+pub fn link_kind(url: &str) -> LinkKind {
+    let url = url.to_lowercase();
+    let without_scheme = URL_SCHEMES.iter().find_map(|scheme| url.strip_prefix(scheme)).unwrap_or(&url);
+    let (host, path) = without_scheme.split_once('/').unwrap_or((without_scheme, ""));
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    if path.ends_with(".pdf") {
+        LinkKind::Document
+    } else if VIDEO_HOSTS.iter().any(|video_host| host == *video_host || host.ends_with(&format!(".{}", video_host))) {
+        LinkKind::Video
+    } else {
+        LinkKind::Web
+    }
+}
+
+/// Parses a `Synonyme` cell. Entries that only differ in case or spacing are kept once
+pub fn parse_aliases(value: &str) -> Vec<String> {
+    let mut aliases: Vec<String> = vec![];
+    for alias in value.split(ALIAS_SEPARATORS).map(str::trim).filter(|alias| !alias.is_empty()) {
+        if !aliases.iter().any(|known| normalize_alias(known) == normalize_alias(alias)) {
+            aliases.push(alias.to_string());
+        }
+    }
+    aliases
+}
+
+/// Parses an `Autor` cell into the names of its co-authors, e.g. "Anna Schmidt und Ben Meyer"
+pub fn parse_authors(value: &str) -> Vec<String> {
+    let value = AUTHOR_WORD_SEPARATORS
+        .iter()
+        .fold(value.to_string(), |value, separator| value.replace(separator, ","));
+    let mut authors: Vec<String> = vec![];
+    for author in value.split(AUTHOR_SEPARATORS) {
+        let author = author.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !author.is_empty() && author.to_lowercase() != UNKNOWN && !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    authors
+}
@@ -1,38 +1,370 @@
 pub mod db; // This import will fail if you have not yet generated your local db.rs file with the prisma-cli-client $(cargo prisma generate)
 pub mod routes;
+pub mod alias;
+pub mod audit;
 pub mod auth;
+pub mod graph;
+pub mod mail;
+pub mod i18n;
+pub mod import;
+pub mod totp;
+pub mod schedule;
 
 #[cfg(test)]
 mod tests {
+    use axum::http::{header::{AUTHORIZATION, COOKIE, ORIGIN}, HeaderMap, HeaderValue};
     use jsonwebtoken::TokenData;
 
-    use crate::{auth::{gen_salt, gen_salt_hash, cmp_salt_hash, gen_jwt, decode_jwt}, db::Role};
+    use crate::{alias::normalize_alias, auth::{hash_password, verify_password, PasswordMatch, AuthError, gen_jwt, decode_jwt, gen_token, hash_token, session_token, session_cookie, JwtConfig, ConfigError, Permission, lockout_duration, ACCOUNT_LOGIN_LIMIT, gen_api_key, is_api_key, api_key_display_prefix, Principal, gen_mfa_jwt, decode_mfa_jwt}, db::{ApiKeyScope, AssessmentLevel, LinkKind, Locale, PublicationStatus, Role}, graph::{canonical_edge_key, rename_edge_conflict, renamed_edge_key}, i18n::pick_translations, import::{link_kind, CsvValueError, parse_aliases, parse_assessment, parse_authors, parse_csv_date, parse_enabler, parse_read_time, parse_related_links}, mail::password_reset_mail, schedule::{is_visible, scheduled_status}, totp, audit::{json_diff, redact_secrets, rekey_snapshot}};
 
     #[test]
-    fn test_gen_salt_hash_and_cmp () {
+    fn test_hash_password_and_verify () {
         let pw = "test123";
         let fake_pw = "fakepw123";
-        let salt = gen_salt();
-        let wrong_salt = gen_salt();
-        // gen_salt returns differnt salts with each call (could be equal by chance, but very unlikely)
-        //println!("salt: {}, wrong_salt: {}", salt, wrong_salt);
-        assert_ne!(salt, wrong_salt); 
-        let salt_hash = gen_salt_hash(&salt, &pw);
-        // different pw leads to different hash
-        assert!(!cmp_salt_hash(fake_pw, &salt_hash));
-        // same salt and pw leads to same hash
-        assert!(cmp_salt_hash(&pw, &salt_hash));
+        let hash = hash_password(pw).unwrap();
+        // hash_password uses a new salt with each call
+        assert_ne!(hash, hash_password(pw).unwrap());
+        assert!(hash.starts_with("$argon2id$"));
+        // different pw is rejected
+        assert_eq!(verify_password(fake_pw, &hash), Ok(PasswordMatch::Invalid));
+        // same pw is accepted
+        assert_eq!(verify_password(pw, &hash), Ok(PasswordMatch::Valid));
+    }
+    #[test]
+    fn test_verify_legacy_salt_hash () {
+        let pw = "test123";
+        // legacy salts are raw bytes mapped to chars and may contain the separator
+        let salt = "a$b\u{e9}cdefg";
+        let digest = ring::digest::digest(&ring::digest::SHA256, format!("{}{}", salt, pw).as_bytes());
+        let legacy = format!("{}${}", salt, base64::encode(digest.as_ref()));
+        assert_eq!(verify_password(pw, &legacy), Ok(PasswordMatch::ValidLegacy));
+        assert_eq!(verify_password("fakepw123", &legacy), Ok(PasswordMatch::Invalid));
+    }
+    #[test]
+    fn test_verify_malformed_hash () {
+        assert_eq!(verify_password("test123", "no_separator"), Err(AuthError::MalformedHash));
+        assert_eq!(verify_password("test123", "salt$not base64!"), Err(AuthError::MalformedHash));
+        assert_eq!(verify_password("test123", "salt$dG9vc2hvcnQ="), Err(AuthError::MalformedHash));
+        assert_eq!(verify_password("test123", "$argon2id$garbage"), Err(AuthError::MalformedHash));
+    }
+    fn jwt_config(keys: &[(&str, &str)]) -> JwtConfig {
+        let keys = keys.iter().map(|(kid, secret)| (kid.to_string(), secret.to_string())).collect();
+        JwtConfig::new(keys, jsonwebtoken::Algorithm::HS512, chrono::Duration::minutes(10), chrono::Duration::days(1)).unwrap()
     }
     #[test]
     fn test_jwt() {
+        let config = jwt_config(&[("k1", "secret1")]);
         let fake_email = "test@test.com";
         let fake_role = Role::Admin;
-        let jwt = gen_jwt(&fake_email, &fake_role).unwrap();
-        if let Ok(TokenData { header: _, claims }) = decode_jwt(&jwt) {
+        let jwt = gen_jwt(&config, fake_email, &fake_role, "session-1").unwrap();
+        if let Ok(TokenData { header, claims }) = decode_jwt(&config, &jwt) {
             //check that we can encode and decode without loss of information
+            assert_eq!(header.kid.as_deref(), Some("k1"));
             assert_eq!(claims.role, fake_role);
             assert_eq!(claims.email, fake_email);
+            assert_eq!(claims.sid, "session-1");
+        } else {
+            panic!("token could not be decoded");
         }
         //TODO: add timestamp tests
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_jwt_key_rotation() {
+        let old_config = jwt_config(&[("k1", "secret1")]);
+        let rotated_config = jwt_config(&[("k2", "secret2"), ("k1", "secret1")]);
+        let retired_config = jwt_config(&[("k2", "secret2")]);
+        let old_jwt = gen_jwt(&old_config, "test@test.com", &Role::Admin, "session-1").unwrap();
+        let new_jwt = gen_jwt(&rotated_config, "test@test.com", &Role::Admin, "session-1").unwrap();
+        // tokens signed with the previous key stay valid while it is listed
+        assert!(decode_jwt(&rotated_config, &old_jwt).is_ok());
+        assert_eq!(decode_jwt(&rotated_config, &new_jwt).unwrap().header.kid.as_deref(), Some("k2"));
+        // and are rejected once it is removed
+        assert!(decode_jwt(&retired_config, &old_jwt).is_err());
+        // same kid but a different secret fails the signature check
+        assert!(decode_jwt(&jwt_config(&[("k1", "other")]), &old_jwt).is_err());
+    }
+    #[test]
+    fn test_jwt_config_validation() {
+        let (minutes, day) = (chrono::Duration::minutes(10), chrono::Duration::days(1));
+        assert!(JwtConfig::new(vec![], jsonwebtoken::Algorithm::HS512, minutes, day).is_err());
+        let duplicate = vec![("k1".to_string(), "a".to_string()), ("k1".to_string(), "b".to_string())];
+        assert!(matches!(JwtConfig::new(duplicate, jsonwebtoken::Algorithm::HS512, minutes, day), Err(ConfigError::Invalid("JWT_KEYS", _))));
+        let keys = vec![("k1".to_string(), "a".to_string())];
+        assert!(matches!(JwtConfig::new(keys.clone(), jsonwebtoken::Algorithm::RS256, minutes, day), Err(ConfigError::Invalid("JWT_ALGORITHM", _))));
+        // refresh tokens have to outlive access tokens
+        assert!(matches!(JwtConfig::new(keys, jsonwebtoken::Algorithm::HS512, day, minutes), Err(ConfigError::Invalid("JWT_REFRESH_DAYS", _))));
+    }
+    #[test]
+    fn test_session_token() {
+        let origins = vec!["https://wlk.example.org".to_string()];
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_token(&headers, &origins), None);
+        // the cookie only counts for requests from a listed origin
+        headers.insert(COOKIE, HeaderValue::from_static("theme=dark; session=cookie.jwt"));
+        assert_eq!(session_token(&headers, &origins), None);
+        headers.insert(ORIGIN, HeaderValue::from_static("https://evil.example.com"));
+        assert_eq!(session_token(&headers, &origins), None);
+        headers.insert(ORIGIN, HeaderValue::from_static("https://wlk.example.org"));
+        assert_eq!(session_token(&headers, &origins), Some("cookie.jwt"));
+        assert_eq!(session_token(&headers, &[]), None);
+        headers.insert(COOKIE, HeaderValue::from_static("session="));
+        assert_eq!(session_token(&headers, &origins), None);
+        // the header wins over the cookie
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic header.jwt"));
+        assert_eq!(session_token(&headers, &origins), None);
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer header.jwt"));
+        assert_eq!(session_token(&headers, &origins), Some("header.jwt"));
+        assert_eq!(session_cookie("cookie.jwt", chrono::Duration::minutes(15)), "session=cookie.jwt; HttpOnly; Secure; SameSite=Strict; Path=/api; Max-Age=900");
+        assert_eq!(session_cookie("", chrono::Duration::zero()), "session=; HttpOnly; Secure; SameSite=Strict; Path=/api; Max-Age=0");
+    }
+    #[test]
+    fn test_gen_token() {
+        let token = gen_token();
+        assert_ne!(token, gen_token());
+        // url safe, so it can travel in a cookie or query string
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+    }
+    #[test]
+    fn test_role_permissions() {
+        assert!(Role::Editor.has_permission(Permission::EditContent));
+        assert!(!Role::Editor.has_permission(Permission::DeleteContent));
+        assert!(!Role::Editor.has_permission(Permission::ManageUsers));
+        assert!(!Role::Viewer.has_permission(Permission::EditContent));
+        assert!(Role::Viewer.has_permission(Permission::ViewAdminData));
+        assert!(Role::Admin.has_permission(Permission::DeleteAllData));
+    }
+    #[test]
+    fn test_password_reset_mail() {
+        let mail = password_reset_mail("test@test.com", "token-123", 60);
+        assert_eq!(mail.to, "test@test.com");
+        assert!(mail.body.contains("?token=token-123"));
+        assert!(mail.body.contains("60 minutes"));
+    }
+    #[test]
+    fn test_lockout_duration() {
+        assert_eq!(lockout_duration(&ACCOUNT_LOGIN_LIMIT, 5), None);
+        assert_eq!(lockout_duration(&ACCOUNT_LOGIN_LIMIT, 6).unwrap().num_seconds(), 30);
+        assert_eq!(lockout_duration(&ACCOUNT_LOGIN_LIMIT, 7).unwrap().num_seconds(), 60);
+        assert_eq!(lockout_duration(&ACCOUNT_LOGIN_LIMIT, 100).unwrap().num_seconds(), 60 * 60);
+    }
+    #[test]
+    fn test_api_keys() {
+        let key = gen_api_key();
+        assert!(is_api_key(&key));
+        assert!(key.starts_with(&api_key_display_prefix(&key)));
+        let principal = Principal::ApiKey { id: "1".into(), name: "nightly".into(), scopes: vec![ApiKeyScope::ContentWrite] };
+        assert!(principal.has_permission(Permission::EditContent));
+        assert!(!principal.has_permission(Permission::DeleteContent));
+        assert!(!principal.has_permission(Permission::ManageUsers));
+        assert!(principal.claims().is_none());
+        assert_eq!(principal.actor(), "apikey:nightly");
+        assert!(!ApiKeyScope::ReadOnly.has_permission(Permission::EditContent));
+    }
+    #[test]
+    fn test_totp() {
+        // RFC 6238 appendix B (SHA-1), truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(totp::hotp(secret, totp::time_step(59) as u64), "287082");
+        assert_eq!(totp::hotp(secret, totp::time_step(1111111109) as u64), "081804");
+        assert_eq!(totp::hotp(secret, totp::time_step(1234567890) as u64), "005924");
+        assert_eq!(totp::hotp(secret, totp::time_step(2000000000) as u64), "279037");
+
+        let encoded = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let step = totp::time_step(1111111109);
+        assert_eq!(totp::verify_code(encoded, "081804", 1111111109, None), Some(step));
+        // previous step still accepted, replays and wrong codes are not
+        assert_eq!(totp::verify_code(encoded, "081804", 1111111109 + 30, None), Some(step));
+        assert_eq!(totp::verify_code(encoded, "081804", 1111111109, Some(step)), None);
+        assert_eq!(totp::verify_code(encoded, "000000", 1111111109, None), None);
+        assert_eq!(totp::verify_code("not base32!", "081804", 1111111109, None), None);
+
+        let uri = totp::provisioning_uri("test@test.com", encoded);
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains("test%40test%2Ecom"));
+        assert!(uri.contains(&format!("secret={}", encoded)));
+    }
+    #[test]
+    fn test_recovery_codes() {
+        let codes = totp::gen_recovery_codes();
+        assert_eq!(codes.len(), totp::RECOVERY_CODE_COUNT);
+        assert_ne!(codes[0], codes[1]);
+        assert_eq!(totp::normalize_recovery_code(&codes[0].to_lowercase()), codes[0].replace('-', ""));
+    }
+    #[test]
+    fn test_mfa_jwt() {
+        let config = jwt_config(&[("k1", "secret1")]);
+        let mfa_token = gen_mfa_jwt(&config, "test@test.com").unwrap();
+        assert_eq!(decode_mfa_jwt(&config, &mfa_token).unwrap().claims.mfa_email, "test@test.com");
+        // neither token works in place of the other
+        assert!(decode_jwt(&config, &mfa_token).is_err());
+        let session_token = gen_jwt(&config, "test@test.com", &Role::Admin, "session-1").unwrap();
+        assert!(decode_mfa_jwt(&config, &session_token).is_err());
+    }
+    #[test]
+    fn test_json_diff() {
+        let before = serde_json::json!({"name": "KI", "views": 1, "url": "a"});
+        let after = serde_json::json!({"name": "KI", "views": 2, "url": "b"});
+        assert_eq!(json_diff(&before, &after), serde_json::json!({
+            "views": {"old": 1, "new": 2},
+            "url": {"old": "a", "new": "b"},
+        }));
+        assert_eq!(json_diff(&before, &before), serde_json::json!({}));
+        // deleted row
+        assert_eq!(json_diff(&before, &serde_json::Value::Null)["name"], serde_json::json!({"old": "KI", "new": null}));
+    }
+    #[test]
+    fn test_redact_secrets() {
+        let input = serde_json::json!({
+            "email": "a@b.de", "password": "pw", "new_password": "pw2", "refresh_token": "t", "code": "123456",
+            "recovery_code": "abcd", "invite_code": "inv", "data": [{"name": "KI", "mfa_token": "m"}]
+        });
+        assert_eq!(redact_secrets(&input), serde_json::json!({
+            "email": "a@b.de", "password": "<redacted>", "new_password": "<redacted>", "refresh_token": "<redacted>", "code": "<redacted>",
+            "recovery_code": "<redacted>", "invite_code": "<redacted>", "data": [{"name": "KI", "mfa_token": "<redacted>"}]
+        }));
+        // node data is logged as is
+        assert_eq!(redact_secrets(&serde_json::json!("KI")), serde_json::json!("KI"));
+    }
+    #[test]
+    fn test_publication_window() {
+        let now = chrono::DateTime::parse_from_rfc3339("2023-01-10T12:00:00+01:00").unwrap();
+        let earlier = Some(now - chrono::Duration::hours(1));
+        let later = Some(now + chrono::Duration::hours(1));
+        assert!(is_visible(PublicationStatus::Published, None, None, now));
+        assert!(is_visible(PublicationStatus::Scheduled, earlier, later, now));
+        assert!(!is_visible(PublicationStatus::Scheduled, later, None, now));
+        assert!(!is_visible(PublicationStatus::Published, None, earlier, now));
+        assert!(!is_visible(PublicationStatus::Draft, None, None, now));
+        assert_eq!(scheduled_status(PublicationStatus::Published, later, now), PublicationStatus::Scheduled);
+        assert_eq!(scheduled_status(PublicationStatus::Published, earlier, now), PublicationStatus::Published);
+        assert_eq!(scheduled_status(PublicationStatus::Draft, later, now), PublicationStatus::Draft);
+    }
+    #[test]
+    fn test_pick_translations() {
+        let rows = vec![
+            ("KI".to_string(), Locale::En, "AI"),
+            ("KI".to_string(), Locale::De, "Künstliche Intelligenz"),
+            ("Daten".to_string(), Locale::De, "Daten"),
+        ];
+        let english = pick_translations(rows.clone(), Locale::En);
+        assert_eq!(english["KI"], "AI");
+        assert_eq!(english["Daten"], "Daten"); // german fallback
+        let german = pick_translations(rows.into_iter().rev(), Locale::De);
+        assert_eq!(german["KI"], "Künstliche Intelligenz");
+        assert_eq!(german.len(), 2);
+    }
+    #[test]
+    fn test_parse_csv_date() {
+        let expected = chrono::DateTime::parse_from_rfc3339("2022-03-07T00:00:00+00:00").unwrap();
+        assert_eq!(parse_csv_date("07.03.2022"), Ok(Some(expected)));
+        assert_eq!(parse_csv_date("3/7/2022"), Ok(Some(expected)));
+        assert_eq!(parse_csv_date("2022-03-07"), Ok(Some(expected)));
+        assert_eq!(parse_csv_date(" 2022-03-07T00:00:00Z "), Ok(Some(expected)));
+        assert_eq!(parse_csv_date(""), Ok(None));
+        assert!(parse_csv_date("März 2022").is_err());
+        assert!(parse_csv_date("31.02.2022").is_err());
+    }
+    #[test]
+    fn test_parse_read_time() {
+        assert_eq!(parse_read_time("5"), Ok(Some(5)));
+        assert_eq!(parse_read_time("5 Min."), Ok(Some(5)));
+        assert_eq!(parse_read_time("ca. 12 Minuten"), Ok(Some(12)));
+        assert_eq!(parse_read_time("etwa 3min"), Ok(Some(3)));
+        assert_eq!(parse_read_time("Unknown"), Ok(None));
+        assert_eq!(parse_read_time(" "), Ok(None));
+        assert!(parse_read_time("2 Std.").is_err());
+        assert!(parse_read_time("lang").is_err());
+    }
+    #[test]
+    fn test_canonical_edge_key() {
+        let key = |source: &str, target: &str, directed| canonical_edge_key(source.to_string(), target.to_string(), directed);
+        assert_eq!(key("Daten", "KI", false), ("Daten".to_string(), "KI".to_string()));
+        assert_eq!(key("KI", "Daten", false), key("Daten", "KI", false));
+        assert_eq!(key("KI", "Daten", true), ("KI".to_string(), "Daten".to_string()));
+        assert_eq!(key("KI", "KI", false), ("KI".to_string(), "KI".to_string()));
+    }
+    #[test]
+    fn test_rename_edge_conflict() {
+        let edge = |source: &str, target: &str, directed| (source.to_string(), target.to_string(), directed);
+        let key = |source: &str, target: &str| (source.to_string(), target.to_string());
+        assert_eq!(renamed_edge_key("Daten", "KI", false, "Daten", "Robotik"), key("KI", "Robotik"));
+        assert_eq!(renamed_edge_key("Daten", "KI", true, "Daten", "Robotik"), key("Robotik", "KI"));
+        assert_eq!(renamed_edge_key("Algorithmen", "KI", false, "Daten", "Robotik"), key("Algorithmen", "KI"));
+        // Daten -> KI directed and Daten – KI undirected, only distinct while Daten sorts before KI
+        let edges = vec![edge("KI", "Daten", true), edge("Daten", "KI", false), edge("Daten", "Algorithmen", true)];
+        assert_eq!(rename_edge_conflict(&edges, "Daten", "Robotik"), Some(key("KI", "Robotik")));
+        assert_eq!(rename_edge_conflict(&edges, "Daten", "Bilddaten"), None);
+        // Byte order as in the swap of renameNode (COLLATE "C"): lower case and umlauts sort after Z
+        assert_eq!(renamed_edge_key("Daten", "Zukunft", false, "Daten", "daten"), key("Zukunft", "daten"));
+        assert_eq!(renamed_edge_key("Daten", "Zukunft", false, "Daten", "Ökologie"), key("Zukunft", "Ökologie"));
+        let edges = vec![edge("Zukunft", "Daten", true), edge("Daten", "Zukunft", false)];
+        assert_eq!(rename_edge_conflict(&edges, "Daten", "Ökologie"), Some(key("Zukunft", "Ökologie")));
+        assert_eq!(rename_edge_conflict(&edges, "Daten", "ökonomie"), Some(key("Zukunft", "ökonomie")));
+    }
+    #[test]
+    fn test_rekey_snapshot_after_rename() {
+        // renameNode("Daten", "Maschinendaten") moves the revisions and leaves a redirect, the snapshots keep the old names
+        let renamed = std::collections::HashMap::from([("Daten".to_string(), "Maschinendaten".to_string())]);
+        let mut node = serde_json::json!({"name": "Daten", "url": "https://example.org/daten", "category_id": "Technik"});
+        rekey_snapshot(&mut node, "Maschinendaten", &[], &renamed);
+        assert_eq!(node, serde_json::json!({"name": "Maschinendaten", "url": "https://example.org/daten", "category_id": "Technik"}));
+        let mut article = serde_json::json!({"name": "Daten und KI", "edge_source_id": "Daten", "edge_target_id": "KI"});
+        rekey_snapshot(&mut article, "Daten und KI", &["edge_source_id", "edge_target_id"], &renamed);
+        assert_eq!(article, serde_json::json!({"name": "Daten und KI", "edge_source_id": "Maschinendaten", "edge_target_id": "KI"}));
+        // The undirected edge was swapped by the rename, restoring looks it up in either order
+        let key = canonical_edge_key(article["edge_source_id"].as_str().unwrap().to_string(), article["edge_target_id"].as_str().unwrap().to_string(), false);
+        assert_eq!(key, ("KI".to_string(), "Maschinendaten".to_string()));
+    }
+    #[test]
+    fn test_parse_assessment() {
+        assert_eq!(parse_assessment("GesellschaftlRelevanz", "Hoch"), Ok(Some(AssessmentLevel::High)));
+        assert_eq!(parse_assessment("GesellschaftlRelevanz", " mittel "), Ok(Some(AssessmentLevel::Medium)));
+        assert_eq!(parse_assessment("GesellschaftlRelevanz", "1"), Ok(Some(AssessmentLevel::Low)));
+        assert_eq!(parse_assessment("GesellschaftlRelevanz", ""), Ok(None));
+        let error = parse_assessment("GesellschaftlRelevanz", "sehr hoch").unwrap_err();
+        assert_eq!(error.column, "GesellschaftlRelevanz");
+        assert_eq!(error.to_string(), "Unrecognized GesellschaftlRelevanz 'sehr hoch'");
+        let clash = CsvValueError { column: "Synonyme", value: "KI".into(), reason: Some("already refers to 'AI'".into()) };
+        assert_eq!(clash.to_string(), "Synonyme 'KI' already refers to 'AI'");
+        assert_eq!(parse_enabler("Ja"), Ok(Some(true)));
+        assert_eq!(parse_enabler("x"), Ok(Some(true)));
+        assert_eq!(parse_enabler("nein"), Ok(Some(false)));
+        assert_eq!(parse_enabler(""), Ok(None));
+        assert!(parse_enabler("vielleicht").is_err());
+    }
+    #[test]
+    fn test_parse_related_links() {
+        let links = parse_related_links("Nudging erklärt: https://www.bidt.digital/nudging/, Vortrag - https://youtu.be/abc123 | https://example.org/studie.pdf?download=1").unwrap();
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].url, "https://www.bidt.digital/nudging/");
+        assert_eq!(links[0].title.as_deref(), Some("Nudging erklärt"));
+        assert_eq!(links[1].title.as_deref(), Some("Vortrag"));
+        assert_eq!(links[1].kind, LinkKind::Video);
+        assert_eq!(links[2].title, None);
+        assert_eq!(links[2].kind, LinkKind::Document);
+        let markdown = parse_related_links("[Studie](https://example.org/a)\n[Blog](http://example.org/b).").unwrap();
+        assert_eq!(markdown[0].url, "https://example.org/a");
+        assert_eq!(markdown[1].title.as_deref(), Some("Blog"));
+        assert_eq!(markdown[1].url, "http://example.org/b");
+        assert_eq!(parse_related_links(""), Ok(vec![]));
+        assert!(parse_related_links("siehe Website").is_err());
+        assert_eq!(link_kind("https://www.youtube.com/watch?v=x"), LinkKind::Video);
+        assert_eq!(link_kind("https://notyoutube.com/a.pdf.html"), LinkKind::Web);
+    }
+    #[test]
+    fn test_aliases() {
+        assert_eq!(normalize_alias(" Künstliche   Intelligenz "), "künstliche intelligenz");
+        assert_eq!(normalize_alias("KI"), normalize_alias("ki"));
+        assert_eq!(parse_aliases("KI, AI; Artificial Intelligence | ki"), vec!["KI", "AI", "Artificial Intelligence"]);
+        assert!(parse_aliases(" ; ").is_empty());
+    }
+    #[test]
+    fn test_parse_authors() {
+        assert_eq!(parse_authors("Anna Schmidt und Ben  Meyer"), vec!["Anna Schmidt", "Ben Meyer"]);
+        assert_eq!(parse_authors("Anna Schmidt, Ben Meyer; Anna Schmidt & C. Weber / D. Roth"), vec!["Anna Schmidt", "Ben Meyer", "C. Weber", "D. Roth"]);
+        assert!(parse_authors("Unknown").is_empty());
+        assert!(parse_authors("").is_empty());
+    }
+}
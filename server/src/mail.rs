@@ -0,0 +1,145 @@
+use std::{env, fmt, fs::OpenOptions, io::Write, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+const DEFAULT_PASSWORD_RESET_URL: &str = "http://localhost:8080/admin/reset";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    /// Mailer configuration or the mail itself is invalid
+    Invalid(String),
+    /// Delivery failed
+    Send(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Invalid(e) => write!(f, "Invalid mail: {}", e),
+            MailError::Send(e) => write!(f, "Sending mail failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Delivery backend for outgoing mail
+#[async_trait]
+pub trait Mailer: Send + Sync + fmt::Debug {
+    async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+// Manual impl so the SMTP credentials never end up in the request log
+impl fmt::Debug for SmtpMailer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpMailer").field("from", &self.from).finish()
+    }
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, port: Option<u16>, credentials: Option<(String, String)>, from: &str) -> Result<Self, MailError> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+            .map_err(|e| MailError::Invalid(e.to_string()))?;
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpMailer {
+            from: from.parse().map_err(|e: lettre::address::AddressError| MailError::Invalid(e.to_string()))?,
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse().map_err(|e: lettre::address::AddressError| MailError::Invalid(e.to_string()))?)
+            .subject(mail.subject)
+            .body(mail.body)
+            .map_err(|e| MailError::Invalid(e.to_string()))?;
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| MailError::Send(e.to_string()))
+    }
+}
+
+/// Appends mails to a file, or prints them to stdout, instead of sending them. For local development and tests
+#[derive(Debug)]
+pub struct FileMailer {
+    path: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        FileMailer { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        let formatted = format!("To: {}\nSubject: {}\n\n{}\n---\n", mail.to, mail.subject, mail.body);
+        match &self.path {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(formatted.as_bytes()))
+                .map_err(|e| MailError::Send(e.to_string())),
+            None => {
+                println!("[MAIL]\n{}", formatted);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// SMTP if `SMTP_HOST` is set (with `SMTP_PORT`, `SMTP_USERNAME`, `SMTP_PASSWORD`, `MAIL_FROM`),
+/// otherwise mails go to `MAIL_FILE` or stdout
+pub fn mailer_from_env() -> Result<Arc<dyn Mailer>, MailError> {
+    if let Ok(host) = env::var("SMTP_HOST") {
+        let port = match env::var("SMTP_PORT") {
+            Ok(port) => Some(port.parse::<u16>().map_err(|e| MailError::Invalid(format!("SMTP_PORT: {}", e)))?),
+            Err(_) => None,
+        };
+        let credentials = env::var("SMTP_USERNAME").ok().zip(env::var("SMTP_PASSWORD").ok());
+        let from = env::var("MAIL_FROM").map_err(|_| MailError::Invalid("MAIL_FROM is required with SMTP_HOST".to_string()))?;
+        return Ok(Arc::new(SmtpMailer::new(&host, port, credentials, &from)?));
+    }
+    Ok(Arc::new(FileMailer::new(env::var("MAIL_FILE").ok().map(PathBuf::from))))
+}
+
+pub fn password_reset_mail(to: &str, token: &str, valid_minutes: i64) -> Mail {
+    let reset_url = env::var("PASSWORD_RESET_URL").unwrap_or_else(|_| DEFAULT_PASSWORD_RESET_URL.to_string());
+    Mail {
+        to: to.to_string(),
+        subject: "bidt Glossar: Passwort zurücksetzen / Reset your password".to_string(),
+        body: format!(
+            "Someone requested a password reset for {}.\n\nOpen {}?token={} within {} minutes to choose a new password.\nIf this was not you, you can ignore this mail.",
+            to, reset_url, token, valid_minutes
+        ),
+    }
+}
@@ -1,51 +1,112 @@
-use std::{sync::Arc};
+use std::{collections::HashMap, env, net::{IpAddr, SocketAddr}, sync::Arc};
 
 use axum::{
-    http::{Method, StatusCode, HeaderMap},
+    extract::ConnectInfo,
+    http::{header::SET_COOKIE, Method, StatusCode, HeaderMap},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use prisma_client_rust::{
     and, or,
+    chrono::{DateTime, FixedOffset, Utc},
     prisma_errors::{query_engine::{RecordNotFound, UniqueKeyViolation}},
-    QueryError, Direction,
+    QueryError, Direction, PrismaValue, raw,
 };
 
-use rspc::{Config, Type};
+use rspc::{Config, ErrorCode, Type};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use strum_macros::Display;
 
-use crate::{db::{self, article, category, edge, node, Role, users, queries, discipline}, auth::{gen_salt, gen_salt_hash, cmp_salt_hash, gen_jwt, check_admin}};
+use crate::{alias::{normalize_alias, resolve_node_name}, db::{self, node_redirect, article, category, edge, node, Role, users, queries, discipline, session, invite, password_reset, login_attempt, login_throttle, api_key, recovery_code, audit_log, revision, node_translation, article_translation, category_translation, discipline_translation, related_link, node_alias, author, tag, ApiKeyScope, AssessmentLevel, Locale, PublicationStatus}, audit::{json_diff, record_audit, record_revision, record_revisioned_change, redact_secrets, rekey_snapshot, BASELINE_REVISION_AUTHOR}, graph::{canonical_edge_key, rename_edge_conflict, resolve_edge_key}, auth::{hash_password, verify_password, PasswordMatch, gen_jwt, decode_jwt, gen_mfa_jwt, decode_mfa_jwt, totp_required, gen_token, hash_token, session_token, session_cookie, gen_api_key, is_api_key, api_key_display_prefix, Claims, JwtConfig, Permission, Principal, lockout_duration, LoginLimit, ACCOUNT_LOGIN_LIMIT, IP_LOGIN_LIMIT}, i18n::{pick_translations, FALLBACK_LOCALE}, mail::{password_reset_mail, Mailer}, schedule::{is_visible, scheduled_status}, totp};
 
 // Define all your requests schemas here
 
 #[derive(Deserialize, Type)]
 struct NodesRequest {
     categories: Option<Vec<String>>,
+    /// German if not set, missing translations fall back to German as well
+    locale: Option<Locale>,
+    /// Only nodes with at least one of these tags
+    tags: Option<Vec<String>>,
+    /// Inclusive bounds in minutes, content without read time is left out once a bound is set
+    min_read_time: Option<i32>,
+    max_read_time: Option<i32>,
+    read_time_order: Option<SortOrder>,
 }
 
 #[derive(Deserialize, Type)]
 struct EdgesRequest {
     categories: Option<Vec<String>>,
+    locale: Option<Locale>,
+    /// Only edges with a matching article, the other articles are left out of the edge as well
+    assessment: Option<AssessmentFilter>,
+    /// Only edges between two nodes with one of these tags, or with an article that has one
+    tags: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Type)]
 struct ArticlesRequest {
     edge_source_id: String,
     edge_target_id: String,
+    locale: Option<Locale>,
+    /// Inclusive, articles without date are left out once a bound is set
+    date_from: Option<DateTime<FixedOffset>>,
+    date_to: Option<DateTime<FixedOffset>>,
+    /// Inclusive bounds in minutes, content without read time is left out once a bound is set
+    min_read_time: Option<i32>,
+    max_read_time: Option<i32>,
+    read_time_order: Option<SortOrder>,
+    assessment: Option<AssessmentFilter>,
+}
+
+/// A single node by name or alias
+#[derive(Deserialize, Type)]
+struct NodeRequest {
+    name: String,
+    locale: Option<Locale>,
+}
+
+/// All authors, or the one with this name
+#[derive(Deserialize, Type)]
+struct AuthorsRequest {
+    name: Option<String>,
 }
 
 #[derive(Deserialize, Type)]
 struct ArticleRequest {
     id: String,
+    locale: Option<Locale>,
+}
+
+/// Node with its texts in the requested locale. `node.teaser_text` is translated as well, `node.name` stays the stable key
+#[derive(Type, Serialize)]
+struct LocalizedNode {
+    node: node::Data,
+    display_name: String,
+    category_display_name: String,
+}
+
+/// Article with its texts in the requested locale. `article.teaser_text` is translated as well, `article.name` stays the stable key
+#[derive(Type, Serialize)]
+struct LocalizedArticle {
+    article: article::Data,
+    display_name: String,
+    discipline_display_name: String,
 }
 
 edge::include!(edge_articles_info {
     articles: select {
        name
        discipline_id
+       status
+       publish_at
+       unpublish_at
+       digital_specificity
+       analog_comparison
+       societal_relevance
+       enabler
     }
 });
 
@@ -54,6 +115,77 @@ struct EdgeWithArticlesInfo(
     edge_articles_info::Data
 );
 
+#[derive(Type, Serialize)]
+struct LocalizedEdge {
+    edge: edge_articles_info::Data,
+    /// Display names of the edge's articles by article name
+    article_display_names: HashMap<String, String>,
+}
+
+/// Editorial assessments an article must have, each list holds the accepted levels.
+/// Articles without the assessment are left out once it is filtered on
+#[derive(Deserialize, Type, Default)]
+struct AssessmentFilter {
+    digital_specificity: Option<Vec<AssessmentLevel>>,
+    analog_comparison: Option<Vec<AssessmentLevel>>,
+    societal_relevance: Option<Vec<AssessmentLevel>>,
+    enabler: Option<bool>,
+}
+
+impl AssessmentFilter {
+    fn where_params(&self) -> Vec<article::WhereParam> {
+        let mut filter = vec![];
+        if let Some(levels) = &self.digital_specificity {
+            filter.push(article::digital_specificity::in_vec(levels.clone()));
+        }
+        if let Some(levels) = &self.analog_comparison {
+            filter.push(article::analog_comparison::in_vec(levels.clone()));
+        }
+        if let Some(levels) = &self.societal_relevance {
+            filter.push(article::societal_relevance::in_vec(levels.clone()));
+        }
+        if let Some(enabler) = self.enabler {
+            filter.push(article::enabler::equals(Some(enabler)));
+        }
+        filter
+    }
+
+    /// Same as `where_params`, for articles that are already loaded
+    fn matches(&self, digital_specificity: Option<AssessmentLevel>, analog_comparison: Option<AssessmentLevel>, societal_relevance: Option<AssessmentLevel>, enabler: Option<bool>) -> bool {
+        let level_matches = |levels: &Option<Vec<AssessmentLevel>>, level: Option<AssessmentLevel>| match levels {
+            Some(levels) => matches!(level, Some(level) if levels.contains(&level)),
+            None => true,
+        };
+        level_matches(&self.digital_specificity, digital_specificity)
+            && level_matches(&self.analog_comparison, analog_comparison)
+            && level_matches(&self.societal_relevance, societal_relevance)
+            && (self.enabler.is_none() || self.enabler == enabler)
+    }
+}
+
+/// Number of public nodes and articles with the tag
+#[derive(Type, Serialize)]
+struct TagUsage {
+    name: String,
+    node_count: i32,
+    article_count: i32,
+}
+
+#[derive(Type, Debug, Deserialize, Clone, Copy)]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl From<SortOrder> for Direction {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Asc => Direction::Asc,
+            SortOrder::Desc => Direction::Desc,
+        }
+    }
+}
+
 #[derive(Type, Display, Debug, Deserialize)]
 enum SearchQueryType {
     #[strum(serialize = "|")]
@@ -67,7 +199,19 @@ enum SearchQueryType {
 #[derive(Deserialize, Type)]
 struct SearchRequest {
     query: String,
-    mode: SearchQueryType
+    mode: SearchQueryType,
+    locale: Option<Locale>,
+    /// Restrict articles to this range (inclusive), nodes have no date
+    date_from: Option<DateTime<FixedOffset>>,
+    date_to: Option<DateTime<FixedOffset>>,
+    /// Inclusive bounds in minutes, nodes and articles without read time is left out once a bound is set
+    min_read_time: Option<i32>,
+    max_read_time: Option<i32>,
+    read_time_order: Option<SortOrder>,
+    /// Applies to articles, nodes are not assessed
+    assessment: Option<AssessmentFilter>,
+    /// Only nodes and articles with at least one of these tags
+    tags: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Type)]
@@ -77,8 +221,8 @@ struct QueriesRequest{
 
 #[derive(Type, Serialize)]
 struct SearchResult {
-    articles: Vec<article::Data>,
-    nodes: Vec<node::Data>
+    articles: Vec<LocalizedArticle>,
+    nodes: Vec<LocalizedNode>
 }
 
 #[derive(Deserialize, Type)]
@@ -97,6 +241,61 @@ struct ViewArticleRequest{
 struct RegisterRequest {
     email: String,
     password: String,
+    invite_code: String,
+}
+
+#[derive(Deserialize, Type)]
+struct CreateInviteRequest {
+    role: Role,
+    valid_days: Option<i64>,
+}
+
+#[derive(Type, Serialize)]
+struct CreateInviteResponse {
+    code: String,
+    expires_at: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize, Type)]
+struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<ApiKeyScope>,
+    /// Keys without expiry stay valid until revoked
+    valid_days: Option<i64>,
+}
+
+#[derive(Type, Serialize)]
+struct CreateApiKeyResponse {
+    key: String,
+    prefix: String,
+    expires_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Deserialize, Type)]
+struct RevokeApiKeyRequest {
+    id: String,
+}
+
+api_key::select!(api_key_info {
+    id
+    name
+    prefix
+    scopes
+    created_by
+    created_at
+    expires_at
+    last_used_at
+    revoked_at
+});
+
+#[derive(Type, Serialize)]
+struct ApiKeyInfo(
+    api_key_info::Data
+);
+
+#[derive(Deserialize, Type)]
+struct RevokeInviteRequest {
+    code_hash: String,
 }
 
 #[derive(Deserialize, Type)]
@@ -108,6 +307,104 @@ struct LoginRequest {
 #[derive(Type, Serialize)]
 struct LoginResponse {
     token: Option<String>,
+    refresh_token: Option<String>,
+    /// Only changePassword and logout are allowed until the password was changed
+    must_change_password: bool,
+    /// Seconds until login attempts are accepted again after too many failures
+    retry_after: Option<i64>,
+    /// Set instead of token when the password was right but a second factor is needed, exchanged in loginTotp
+    mfa_token: Option<String>,
+    /// Only totpEnroll, totpConfirm and logout are allowed until two-factor authentication is set up
+    must_enroll_totp: bool,
+}
+
+impl LoginResponse {
+    fn rejected(retry_after: Option<i64>) -> Self {
+        LoginResponse {token: None, refresh_token: None, must_change_password: false, retry_after, mfa_token: None, must_enroll_totp: false}
+    }
+}
+
+#[derive(Deserialize, Type)]
+struct LoginTotpRequest {
+    mfa_token: String,
+    /// Code from the authenticator app or one of the recovery codes
+    code: String,
+}
+
+#[derive(Type, Serialize)]
+struct TotpEnrollResponse {
+    secret: String,
+    /// otpauth:// URI, to be shown as QR code
+    uri: String,
+}
+
+#[derive(Deserialize, Type)]
+struct TotpConfirmRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Type)]
+struct LoginAttemptsRequest {
+    email: Option<String>,
+    ip: Option<String>,
+    failed_only: bool,
+}
+
+#[derive(Deserialize, Type)]
+struct ClearLockoutRequest {
+    key: String,
+}
+
+#[derive(Deserialize, Type)]
+struct PasswordResetRequest {
+    email: String,
+}
+
+#[derive(Deserialize, Type)]
+struct ConfirmPasswordResetRequest {
+    token: String,
+    new_password: String,
+}
+
+#[derive(Deserialize, Type)]
+struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+users::select!(user_info {
+    email
+    role
+    disabled
+    must_change_password
+    created_at
+});
+
+#[derive(Type, Serialize)]
+struct UserInfo(
+    user_info::Data
+);
+
+#[derive(Deserialize, Type)]
+struct UserRequest {
+    email: String,
+}
+
+#[derive(Deserialize, Type)]
+struct SetUserRoleRequest {
+    email: String,
+    role: Role,
+}
+
+#[derive(Deserialize, Type)]
+struct SetUserDisabledRequest {
+    email: String,
+    disabled: bool,
+}
+
+#[derive(Deserialize, Type)]
+struct RefreshRequest {
+    refresh_token: String,
 }
 
 #[derive(Type, Display, Debug, Deserialize)]
@@ -122,91 +419,301 @@ enum ModType {
     Upsert,
 }
 
-#[derive(Deserialize, Type)]
-struct DeleteAllDataRequest {
-    token: String
-}
-
 #[derive(Deserialize, Type)]
 struct ModNodeRequest {
-    token: String,
     op: ModType,
     data: node::Data
 }
 
 #[derive(Deserialize, Type)]
 struct ModCategoryRequest {
-    token: String,
     op: ModType,
     data: category::Data
 }
 
 #[derive(Deserialize, Type)]
 struct ModDisciplineRequest {
-    token: String,
     op: ModType,
     data: discipline::Data
 }
 
 #[derive(Deserialize, Type)]
 struct ModEdgeRequest {
-    token: String,
     op: ModType,
     data: edge::Data
 }
 
 #[derive(Deserialize, Type)]
 struct ModArticleRequest {
-    token: String,
     op: ModType,
     data: article::Data
 }
 
+/// Entities with revision history
+#[derive(Type, Display, Debug, Deserialize, Clone, Copy)]
+enum RevisionEntity {
+    #[strum(serialize = "Node")]
+    Node,
+    #[strum(serialize = "Article")]
+    Article,
+}
+
+#[derive(Deserialize, Type)]
+struct RevisionsRequest {
+    entity: RevisionEntity,
+    entity_key: String,
+}
+
+#[derive(Deserialize, Type)]
+struct RevisionDiffRequest {
+    entity: RevisionEntity,
+    entity_key: String,
+    from: i32,
+    to: i32,
+}
+
+#[derive(Deserialize, Type)]
+struct RestoreRevisionRequest {
+    entity: RevisionEntity,
+    entity_key: String,
+    number: i32,
+}
+
+/// Identifies a node, edge or article for the review workflow
+#[derive(Deserialize, Type)]
+enum ContentRef {
+    Node { name: String },
+    Edge { source_id: String, target_id: String },
+    Article { name: String },
+}
+
+#[derive(Deserialize, Type)]
+struct AdminContentRequest {
+    /// All statuses if not set
+    status: Option<PublicationStatus>,
+}
+
+#[derive(Deserialize, Type)]
+struct SubmitForReviewRequest {
+    content: ContentRef,
+}
+
+#[derive(Deserialize, Type)]
+struct ReviewContentRequest {
+    content: ContentRef,
+    /// Publishes the content, otherwise it goes back to draft
+    approve: bool,
+}
+
+#[derive(Deserialize, Type)]
+struct AuditLogRequest {
+    /// User email, or `apikey:<name>` for API keys
+    actor: Option<String>,
+    entity: Option<String>,
+    entity_key: Option<String>,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Deserialize, Type)]
+struct ModNodeTranslationRequest {
+    op: ModType,
+    data: node_translation::Data
+}
+
+#[derive(Deserialize, Type)]
+struct ModArticleTranslationRequest {
+    op: ModType,
+    data: article_translation::Data
+}
+
+#[derive(Deserialize, Type)]
+struct ModCategoryTranslationRequest {
+    op: ModType,
+    data: category_translation::Data
+}
+
+#[derive(Deserialize, Type)]
+struct ModDisciplineTranslationRequest {
+    op: ModType,
+    data: discipline_translation::Data
+}
+
+/// `normalized` is derived from `alias` and may be left empty
+#[derive(Deserialize, Type)]
+struct ModNodeAliasRequest {
+    op: ModType,
+    data: node_alias::Data
+}
+
+#[derive(Deserialize, Type)]
+struct RenameNodeRequest {
+    name: String,
+    new_name: String,
+}
+
+/// The id is ignored on create
+#[derive(Deserialize, Type)]
+struct ModTagRequest {
+    op: ModType,
+    data: tag::Data
+}
+
+/// Replaces the tags of the node
+#[derive(Deserialize, Type)]
+struct SetNodeTagsRequest {
+    node_name: String,
+    tag_ids: Vec<i32>,
+}
+
+/// Replaces the tags of the article
+#[derive(Deserialize, Type)]
+struct SetArticleTagsRequest {
+    article_name: String,
+    tag_ids: Vec<i32>,
+}
+
+/// The id is ignored on create
+#[derive(Deserialize, Type)]
+struct ModAuthorRequest {
+    op: ModType,
+    data: author::Data
+}
+
+/// Replaces the authors of the node
+#[derive(Deserialize, Type)]
+struct SetNodeAuthorsRequest {
+    node_name: String,
+    author_ids: Vec<i32>,
+}
+
+/// Replaces the authors of the article
+#[derive(Deserialize, Type)]
+struct SetArticleAuthorsRequest {
+    article_name: String,
+    author_ids: Vec<i32>,
+}
+
+/// Exactly one of `node_name` and `article_name` is set. The id is ignored on create
+#[derive(Deserialize, Type)]
+struct ModRelatedLinkRequest {
+    op: ModType,
+    data: related_link::Data
+}
+
 #[derive(Type, Serialize)]
 struct ModResponse {
     error: Option<String>
 }
-type Ctx = (Arc<Mutex<db::PrismaClient>>, HeaderMap, Method);
+const DEFAULT_INVITE_DAYS: i64 = 7;
+const MAX_INVITE_DAYS: i64 = 30;
+const PASSWORD_RESET_MINUTES: i64 = 60;
+const LOGIN_ATTEMPTS_PAGE_SIZE: i64 = 200;
+const AUDIT_LOG_PAGE_SIZE: i64 = 500;
+
+type Ctx = (Arc<Mutex<db::PrismaClient>>, Arc<JwtConfig>, Arc<dyn Mailer>, HeaderMap, Method, IpAddr);
+
+/// Permission each procedure behind the authentication middleware requires, checked centrally by the middleware.
+/// Procedures missing here are rejected
+fn required_permission(path: &str, input: &serde_json::Value) -> Option<Permission> {
+    let is_delete = input.get("op").and_then(|op| op.as_str()) == Some(ModType::Delete.to_string().as_str());
+    match path {
+        "logout" | "logoutEverywhere" | "changePassword" => Some(Permission::OwnAccount),
+        "queries" | "all_queries" => Some(Permission::ViewAdminData),
+        "createInvite" | "invites" | "revokeInvite" => Some(Permission::ManageUsers),
+        "users" | "setUserRole" | "setUserDisabled" | "deleteUser" | "forcePasswordReset" => Some(Permission::ManageUsers),
+        "loginLockouts" | "loginAttempts" | "clearLockout" => Some(Permission::ManageUsers),
+        "createApiKey" | "apiKeys" | "revokeApiKey" => Some(Permission::ManageUsers),
+        "totpEnroll" | "totpConfirm" => Some(Permission::OwnAccount),
+        "resetTotp" => Some(Permission::ManageUsers),
+        "modNode" | "modEdge" | "modArticle" | "modCategory" | "modDiscipline" if is_delete => Some(Permission::DeleteContent),
+        "modNode" | "modEdge" | "modArticle" => Some(Permission::EditContent),
+        "modCategory" | "modDiscipline" => Some(Permission::EditTaxonomy),
+        "modNodeTranslation" | "modArticleTranslation" | "modCategoryTranslation" | "modDisciplineTranslation" if is_delete => Some(Permission::DeleteContent),
+        "modNodeTranslation" | "modArticleTranslation" => Some(Permission::EditContent),
+        "modCategoryTranslation" | "modDisciplineTranslation" => Some(Permission::EditTaxonomy),
+        "modRelatedLink" | "modNodeAlias" | "modAuthor" if is_delete => Some(Permission::DeleteContent),
+        "modRelatedLink" | "modNodeAlias" | "modAuthor" | "setNodeAuthors" | "setArticleAuthors" => Some(Permission::EditContent),
+        "renameNode" => Some(Permission::EditContent),
+        "modTag" if is_delete => Some(Permission::DeleteContent),
+        "modTag" => Some(Permission::EditTaxonomy),
+        "setNodeTags" | "setArticleTags" => Some(Permission::EditContent),
+        "deleteAllData" => Some(Permission::DeleteAllData),
+        "auditLog" => Some(Permission::ViewAuditLog),
+        "revisions" | "pendingRevisions" | "revisionDiff" => Some(Permission::ViewAdminData),
+        "restoreRevision" => Some(Permission::EditContent),
+        "adminNodes" | "adminEdges" | "adminArticles" => Some(Permission::ViewAdminData),
+        "submitForReview" => Some(Permission::EditContent),
+        "reviewContent" => Some(Permission::Publish),
+        _ => None,
+    }
+}
+
+/// Context of the procedures behind the authentication middleware
+#[derive(Debug)]
+struct AuthCtx {
+    db: Arc<Mutex<db::PrismaClient>>,
+    mailer: Arc<dyn Mailer>,
+    principal: Principal,
+}
 /*
 /api/user => GET, POST
 */
-pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
+pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>, jwt_config: Arc<JwtConfig>, mailer: Arc<dyn Mailer>) -> Router {
+    // Only behind a reverse proxy the forwarded header can be trusted, otherwise clients could pick their own ip
+    let trust_proxy_headers = env::var("TRUST_PROXY_HEADERS").map_or(false, |v| v == "true");
     // Internal router
     let rspc_router = rspc::Router::<Ctx>::new()
         .config(Config::new().export_ts_bindings("../website/src/types/bindings.ts"))
-        // logging
+        // logging: headers are left out and credentials masked, they carry session tokens, API keys and passwords
         .middleware(|mw| {
             mw.middleware(|mw| async move {
-                let state = (mw.req.clone(), mw.ctx.clone(), mw.input.clone());
+                let (_, _, _, _, method, client_ip) = mw.ctx.clone();
+                let state = (mw.req.path.clone(), method, client_ip, redact_secrets(&mw.input));
                 Ok(mw.with_state(state))
             })
             .resp(|state, result| async move {
                 println!(
-                    "[LOG: {}] req='{:?}' ctx='{:?}'  input='{:?}'",
-                    chrono::Utc::now(), state.0, state.1, state.2 // could print result as well
+                    "[LOG: {}] {} {} from {} input='{}'",
+                    chrono::Utc::now(), state.1, state.0, state.2, state.3
                 );
                 Ok(result)
             })
         })
         .query("nodes", |t| {
-            t(|(db, _headers, _method), node_request: NodesRequest| async move {
+            t(|(db, _jwt_config, _mailer, _headers, _method, _client_ip), node_request: NodesRequest| async move {
                 let db = db.lock().await;
-                let cat_filter = if let Some(cat) = node_request.categories {
+                let mut cat_filter = if let Some(cat) = node_request.categories {
                     vec![node::category::is(vec![category::name::in_vec(cat)])]
                 } else {
                     vec![] //No category returns all edges
                 };
-                db.node()
+                cat_filter.push(visible_node(Utc::now().into()));
+                cat_filter.extend(node_read_time_filter(node_request.min_read_time, node_request.max_read_time));
+                if let Some(tags) = node_request.tags {
+                    cat_filter.push(node::tags::some(vec![tag::name::in_vec(tags)]));
+                }
+                let mut query = db.node()
                     .find_many(cat_filter)
+                    .with(node::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                    .with(node::aliases::fetch(vec![]))
+                    .with(node::authors::fetch(vec![]))
+                    .with(node::tags::fetch(vec![]));
+                if let Some(order) = node_request.read_time_order {
+                    query = query.order_by(node::read_time_minutes::order(order.into()));
+                }
+                let nodes = query
                     .exec()
+                    .await?;
+                localize_nodes(&db, nodes, node_request.locale.unwrap_or(FALLBACK_LOCALE))
                     .await
                     .map_err(Into::into)
             })
         })
         .query("edges", |t| {
-            t(|(db, _, _), edge_request: EdgesRequest| async move {
+            t(|(db, _, _, _, _, _), edge_request: EdgesRequest| async move {
                 let db = db.lock().await;
-                let cat_filter = if let Some(cat) = edge_request.categories {
+                let mut cat_filter = if let Some(cat) = edge_request.categories {
                     //vec![edge::src::category::is(vec![category::name::in_vec(cat)])]
                     vec![and![
                         edge::source::is(vec![node::category_id::in_vec(cat.clone())]),
@@ -215,44 +722,136 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                 } else {
                     vec![] //No category returns all edges
                 };
-                Ok(db.edge()
+                // Edges to unpublished nodes would dangle in the graph
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                cat_filter.extend([
+                    edge::status::equals(PublicationStatus::Published),
+                    edge::source::is(vec![visible_node(now)]),
+                    edge::target::is(vec![visible_node(now)]),
+                ]);
+                let assessment = edge_request.assessment.unwrap_or_default();
+                let assessment_filter = assessment.where_params();
+                if !assessment_filter.is_empty() {
+                    let mut article_filter = vec![visible_article(now)];
+                    article_filter.extend(assessment_filter);
+                    cat_filter.push(edge::articles::some(article_filter));
+                }
+                if let Some(tags) = edge_request.tags {
+                    cat_filter.push(or![
+                        and![
+                            edge::source::is(vec![node::tags::some(vec![tag::name::in_vec(tags.clone())])]),
+                            edge::target::is(vec![node::tags::some(vec![tag::name::in_vec(tags.clone())])]),
+                        ],
+                        edge::articles::some(vec![visible_article(now), article::tags::some(vec![tag::name::in_vec(tags)])]),
+                    ]);
+                }
+                let edges = db.edge()
                     .find_many(cat_filter)
                     .include(edge_articles_info::include())
                     .exec()
                     .await?
                     .into_iter()
-                    .map(EdgeWithArticlesInfo)
-                    .collect::<Vec<EdgeWithArticlesInfo>>())
+                    .map(|mut edge| {
+                        edge.articles.retain(|article| {
+                            is_visible(article.status, article.publish_at, article.unpublish_at, now)
+                                && assessment.matches(article.digital_specificity, article.analog_comparison, article.societal_relevance, article.enabler)
+                        });
+                        edge
+                    })
+                    .collect::<Vec<_>>();
+                let article_names = edges
+                    .iter()
+                    .flat_map(|edge| edge.articles.iter().map(|article| article.name.clone()))
+                    .collect();
+                let translations = article_translations(&db, article_names, edge_request.locale.unwrap_or(FALLBACK_LOCALE)).await?;
+                Ok(edges
+                    .into_iter()
+                    .map(|edge| LocalizedEdge {
+                        article_display_names: edge.articles
+                            .iter()
+                            .map(|article| (
+                                article.name.clone(),
+                                translations.get(&article.name).map_or_else(|| article.name.clone(), |translation| translation.display_name.clone()),
+                            ))
+                            .collect(),
+                        edge,
+                    })
+                    .collect::<Vec<LocalizedEdge>>())
             })
         })
         .query("articles", |t| {
             t(
-                |(db, _, _), articles_request: ArticlesRequest| async move {
+                |(db, _, _, _, _, _), articles_request: ArticlesRequest| async move {
                     let db = db.lock().await;
-                    let cat_filter = vec![and![article::edge_source_id::equals(articles_request.edge_source_id.clone()), article::edge_target_id::equals(articles_request.edge_target_id.clone())]];
-                    db.article()
+                    // The nodes may be given by alias and undirected edges in either order
+                    let source_id = resolve_node_name(&db, &articles_request.edge_source_id).await?.unwrap_or(articles_request.edge_source_id);
+                    let target_id = resolve_node_name(&db, &articles_request.edge_target_id).await?.unwrap_or(articles_request.edge_target_id);
+                    let (source_id, target_id) = resolve_edge_key(&db, &source_id, &target_id, true).await?.unwrap_or((source_id, target_id));
+                    let mut cat_filter = vec![
+                        and![article::edge_source_id::equals(source_id), article::edge_target_id::equals(target_id)],
+                        visible_article(Utc::now().into()),
+                    ];
+                    cat_filter.extend(article_date_filter(articles_request.date_from, articles_request.date_to));
+                    cat_filter.extend(article_read_time_filter(articles_request.min_read_time, articles_request.max_read_time));
+                    if let Some(assessment) = articles_request.assessment {
+                        cat_filter.extend(assessment.where_params());
+                    }
+                    let mut query = db.article()
                         .find_many(cat_filter)
+                        .with(article::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                        .with(article::authors::fetch(vec![]))
+                        .with(article::tags::fetch(vec![]));
+                    if let Some(order) = articles_request.read_time_order {
+                        query = query.order_by(article::read_time_minutes::order(order.into()));
+                    }
+                    let articles = query
                         .exec()
+                        .await?;
+                    localize_articles(&db, articles, articles_request.locale.unwrap_or(FALLBACK_LOCALE))
                         .await
                         .map_err(Into::into)
                 },
             )
         })
+        .query("node", |t| {
+            t(|(db, _, _, _, _, _), node_request: NodeRequest| async move {
+                let db = db.lock().await;
+                let name = match resolve_node_name(&db, &node_request.name).await? {
+                    Some(name) => name,
+                    None => return Ok(None),
+                };
+                let nodes = db.node()
+                    .find_many(vec![node::name::equals(name), visible_node(Utc::now().into())])
+                    .with(node::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                    .with(node::aliases::fetch(vec![]))
+                    .with(node::authors::fetch(vec![]))
+                    .with(node::tags::fetch(vec![]))
+                    .exec()
+                    .await?;
+                let nodes = localize_nodes(&db, nodes, node_request.locale.unwrap_or(FALLBACK_LOCALE)).await?;
+                Ok(nodes.into_iter().next())
+            })
+        })
         .query("article", |t| {
             t(
-                |(db, _, _), article_request: ArticleRequest| async move {
+                |(db, _, _, _, _, _), article_request: ArticleRequest| async move {
                     let db = db.lock().await;
-                    let cat_filter = vec![article::name::equals(article_request.id)];
-                    db.article()
+                    let cat_filter = vec![article::name::equals(article_request.id), visible_article(Utc::now().into())];
+                    let articles = db.article()
                         .find_many(cat_filter)
+                        .with(article::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                        .with(article::authors::fetch(vec![]))
+                        .with(article::tags::fetch(vec![]))
                         .exec()
+                        .await?;
+                    localize_articles(&db, articles, article_request.locale.unwrap_or(FALLBACK_LOCALE))
                         .await
                         .map_err(Into::into)
                 },
             )
         })
         .query("categories", |t| {
-            t(|(db, _, _), _: ()| async move {
+            t(|(db, _, _, _, _, _), _: ()| async move {
                 let db = db.lock().await;
                 db.category()
                     .find_many(vec![])
@@ -261,8 +860,47 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                     .map_err(Into::into)
             })
         })
+        .query("authors", |t| {
+            t(|(db, _, _, _, _, _), authors_request: AuthorsRequest| async move {
+                let db = db.lock().await;
+                // Contributions are the public nodes and articles only
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                db.author()
+                    .find_many(authors_request.name.map(author::name::equals).into_iter().collect())
+                    .with(author::nodes::fetch(vec![visible_node(now)]))
+                    .with(author::articles::fetch(vec![visible_article(now)]))
+                    .order_by(author::name::order(Direction::Asc))
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("tagCloud", |t| {
+            t(|(db, _, _, _, _, _), _: ()| async move {
+                let db = db.lock().await;
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                let tags = db.tag()
+                    .find_many(vec![])
+                    .with(tag::nodes::fetch(vec![visible_node(now)]))
+                    .with(tag::articles::fetch(vec![visible_article(now)]))
+                    .exec()
+                    .await?;
+                let mut usages = tags
+                    .into_iter()
+                    .map(|tag| TagUsage {
+                        node_count: tag.nodes.map_or(0, |nodes| nodes.len() as i32),
+                        article_count: tag.articles.map_or(0, |articles| articles.len() as i32),
+                        name: tag.name,
+                    })
+                    .filter(|usage| usage.node_count + usage.article_count > 0)
+                    .collect::<Vec<_>>();
+                // Most used first, ties alphabetically
+                usages.sort_by(|a, b| (b.node_count + b.article_count).cmp(&(a.node_count + a.article_count)).then_with(|| a.name.cmp(&b.name)));
+                Ok(usages)
+            })
+        })
         .query("disciplines", |t| {
-            t(|(db, _, _), _: ()| async move {
+            t(|(db, _, _, _, _, _), _: ()| async move {
                 let db = db.lock().await;
                 db.discipline()
                     .find_many(vec![])
@@ -272,7 +910,7 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
             })
         })
         .query("search", |t| {
-            t(|(db, _, _), search_request: SearchRequest| async move {
+            t(|(db, _, _, _, _, _), search_request: SearchRequest| async move {
                 let db = db.lock().await;
                 let postgres_search_query = search_request.query
                     .split(' ')
@@ -281,32 +919,71 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                     .reduce(|acc, word| {
                         format!("{}{}{}",acc,search_request.mode,word)
                     })
-                    .unwrap();
-                println!("{}", postgres_search_query);
-                let articles = db.article()
-                    .find_many(vec![
-                        or![
-                            article::name::search(postgres_search_query.clone()),
-                            article::author::search(postgres_search_query.clone()),
-                            article::discipline_id::search(postgres_search_query.clone()),
-                            article::teaser_text::search(postgres_search_query.clone()),
-                        ]
-                    ])
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::BadRequest, "The search query is empty".into()))?;
+                let mut article_filter = vec![
+                    or![
+                        article::name::search(postgres_search_query.clone()),
+                        article::authors::some(vec![author::name::search(postgres_search_query.clone())]),
+                        article::tags::some(vec![tag::name::search(postgres_search_query.clone())]),
+                        article::discipline_id::search(postgres_search_query.clone()),
+                        article::teaser_text::search(postgres_search_query.clone()),
+                        article::translations::some(vec![or![
+                            article_translation::display_name::search(postgres_search_query.clone()),
+                            article_translation::teaser_text::search(postgres_search_query.clone()),
+                        ]]),
+                    ],
+                    visible_article(Utc::now().into()),
+                ];
+                article_filter.extend(article_date_filter(search_request.date_from, search_request.date_to));
+                article_filter.extend(article_read_time_filter(search_request.min_read_time, search_request.max_read_time));
+                if let Some(assessment) = &search_request.assessment {
+                    article_filter.extend(assessment.where_params());
+                }
+                if let Some(tags) = &search_request.tags {
+                    article_filter.push(article::tags::some(vec![tag::name::in_vec(tags.clone())]));
+                }
+                let mut article_query = db.article()
+                    .find_many(article_filter)
+                    .with(article::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                    .with(article::authors::fetch(vec![]))
+                    .with(article::tags::fetch(vec![]));
+                if let Some(order) = search_request.read_time_order {
+                    article_query = article_query.order_by(article::read_time_minutes::order(order.into()));
+                }
+                let articles = article_query
                     .exec()
-                    .await
-                    .unwrap(); //TODO: this is temp, do error handling
-                let nodes = db.node()
-                    .find_many(vec![
-                        or![
-                            node::name::search(postgres_search_query.clone()),
-                            node::author::search(postgres_search_query.clone()),
-                            node::category_id::search(postgres_search_query.clone()),
-                            node::teaser_text::search(postgres_search_query.clone()),
-                        ]
-                    ])
+                    .await?;
+                let mut node_filter = vec![
+                    or![
+                        node::name::search(postgres_search_query.clone()),
+                        node::authors::some(vec![author::name::search(postgres_search_query.clone())]),
+                        node::category_id::search(postgres_search_query.clone()),
+                        node::teaser_text::search(postgres_search_query.clone()),
+                        node::aliases::some(vec![node_alias::alias::search(postgres_search_query.clone())]),
+                        node::tags::some(vec![tag::name::search(postgres_search_query.clone())]),
+                        node::translations::some(vec![or![
+                            node_translation::display_name::search(postgres_search_query.clone()),
+                            node_translation::teaser_text::search(postgres_search_query.clone()),
+                        ]]),
+                    ],
+                    visible_node(Utc::now().into()),
+                ];
+                node_filter.extend(node_read_time_filter(search_request.min_read_time, search_request.max_read_time));
+                if let Some(tags) = &search_request.tags {
+                    node_filter.push(node::tags::some(vec![tag::name::in_vec(tags.clone())]));
+                }
+                let mut node_query = db.node()
+                    .find_many(node_filter)
+                    .with(node::links::fetch(vec![]).order_by(related_link::position::order(Direction::Asc)))
+                    .with(node::aliases::fetch(vec![]))
+                    .with(node::authors::fetch(vec![]))
+                    .with(node::tags::fetch(vec![]));
+                if let Some(order) = search_request.read_time_order {
+                    node_query = node_query.order_by(node::read_time_minutes::order(order.into()));
+                }
+                let nodes = node_query
                     .exec()
-                    .await
-                    .unwrap(); //TODO: this is temp, do error handling
+                    .await?;
                 //save query for later analysis
                 //TODO: check for errors and log if necessary
                 let _ = db
@@ -323,12 +1000,303 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                     )
                     .exec()
                     .await;
-                SearchResult {articles, nodes}
+                let locale = search_request.locale.unwrap_or(FALLBACK_LOCALE);
+                let articles = localize_articles(&db, articles, locale).await?;
+                let nodes = localize_nodes(&db, nodes, locale).await?;
+                Ok(SearchResult {articles, nodes})
             })
         })
-        .query("queries", |t| {
-            t(|(db, _, _), queries_request: QueriesRequest| async move {
-                //TODO: add admin token validation
+        .query("view_node", |t| {
+            t(|(db, _, _, _, _, _), view_node_request: ViewNodeRequest| async move {
+                let view_update = 
+                    if view_node_request.is_hover {
+                        node::wlk_hovers::increment(1)
+                    } else {
+                        node::wlk_clicks::increment(1)
+                    };
+                let db = db.lock().await;
+                let name = resolve_node_name(&db, &view_node_request.name).await?.unwrap_or(view_node_request.name);
+                db
+                    .node()
+                    .update(
+                        node::name::equals(name),
+                        vec![view_update]
+                    )
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("view_article", |t| {
+            t(|(db, _, _, _, _, _), view_article_request: ViewArticleRequest| async move {
+                let view_update = 
+                    if view_article_request.is_hover {
+                        article::wlk_hovers::increment(1)
+                    } else {
+                        article::wlk_clicks::increment(1)
+                    };
+                let db = db.lock().await;
+                db
+                    .article()
+                    .update(
+                        article::name::equals(view_article_request.name),
+                        vec![view_update]
+                    )
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("register", |t| {
+            t(|(db, jwt_config, _, _, _, _), register_request: RegisterRequest| async move {
+                let db_hash = hash_password(&register_request.password)?;
+                let db = db.lock().await;
+                let existing_user = db
+                    .users()
+                    .find_unique(users::email::equals(register_request.email.clone()))
+                    .exec()
+                    .await?;
+                if existing_user.is_some() {
+                    return Err(rspc::Error::new(ErrorCode::Conflict, "User already exists".into()));
+                }
+                let code_hash = hash_token(&register_request.invite_code);
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                let open_invite = || vec![
+                    invite::code_hash::equals(code_hash.clone()),
+                    invite::used_at::equals(None),
+                    invite::expires_at::gt(now),
+                ];
+                let invalid_invite = || rspc::Error::new(ErrorCode::Forbidden, "Invalid or expired invite".into());
+                let invite = db.invite().find_first(open_invite()).exec().await?.ok_or_else(invalid_invite)?;
+                // Claim and account in one transaction, so a failed create doesn't use up the invite.
+                // The claim is a conditional update, so the invite can only be redeemed once
+                let (claimed, user) = db
+                    ._batch((
+                        db.invite().update_many(
+                            open_invite(),
+                            vec![
+                                invite::used_at::set(Some(now)),
+                                invite::used_by::set(Some(register_request.email.clone())),
+                            ]
+                        ),
+                        db.users().create(
+                            register_request.email,
+                            db_hash,
+                            invite.role,
+                            vec![]
+                        ),
+                    ))
+                    .await?;
+                if claimed != 1 {
+                    // Redeemed by another server process in the meantime
+                    db.users().delete(users::email::equals(user.email)).exec().await?;
+                    return Err(invalid_invite());
+                }
+                create_session(&db, &jwt_config, &user).await
+            })
+        })
+        .query("login", |t| {
+            t(|(db, jwt_config, _, _, _, client_ip), login_request: LoginRequest| async move {
+                let db = db.lock().await;
+                let throttle_keys = vec![
+                    throttle_key_email(&login_request.email),
+                    throttle_key_ip(&client_ip),
+                ];
+                // Locked out: don't even look at the password
+                if let Some(retry_after) = login_retry_after(&db, &throttle_keys).await? {
+                    record_login_attempt(&db, &login_request.email, &client_ip, false).await?;
+                    return Ok(LoginResponse::rejected(Some(retry_after)))
+                }
+                if let Some(user) = db.users()
+                    .find_unique(users::email::equals(login_request.email.clone()))
+                    .exec()
+                    .await?
+                {
+                    let password_match = verify_password(&login_request.password, &user.hash)?;
+                    if password_match != PasswordMatch::Invalid && !user.disabled {
+                        if password_match == PasswordMatch::ValidLegacy {
+                            // Migrate the old salted SHA-256 record now that we know the plain password
+                            db
+                                .users()
+                                .update(
+                                    users::email::equals(user.email.clone()),
+                                    vec![users::hash::set(hash_password(&login_request.password)?)]
+                                )
+                                .exec()
+                                .await?;
+                        }
+                        if user.totp_enabled {
+                            // Valid pw, the session is only opened after the second factor
+                            let mfa_token = gen_mfa_jwt(&jwt_config, &user.email)
+                                .map_err(|e| rspc::Error::new(ErrorCode::InternalServerError, e.to_string()))?;
+                            return Ok(LoginResponse {mfa_token: Some(mfa_token), ..LoginResponse::rejected(None)})
+                        }
+                        // Valid pw, open a new session
+                        return complete_login(&db, &jwt_config, &user, &client_ip).await
+                    }
+                }
+                record_login_attempt(&db, &login_request.email, &client_ip, false).await?;
+                let retry_after = register_login_failure(&db, &throttle_keys).await?;
+                Ok(LoginResponse::rejected(retry_after))
+            })
+        })
+        .query("loginTotp", |t| {
+            t(|(db, jwt_config, _, _, _, client_ip), login_totp_request: LoginTotpRequest| async move {
+                let email = decode_mfa_jwt(&jwt_config, &login_totp_request.mfa_token)
+                    .map_err(|e| rspc::Error::new(ErrorCode::Unauthorized, e.to_string()))?
+                    .claims
+                    .mfa_email;
+                let db = db.lock().await;
+                // Codes are short, so they count against the same limits as passwords
+                let throttle_keys = vec![
+                    throttle_key_email(&email),
+                    throttle_key_ip(&client_ip),
+                ];
+                if let Some(retry_after) = login_retry_after(&db, &throttle_keys).await? {
+                    record_login_attempt(&db, &email, &client_ip, false).await?;
+                    return Ok(LoginResponse::rejected(Some(retry_after)))
+                }
+                let user = db
+                    .users()
+                    .find_first(vec![
+                        users::email::equals(email.clone()),
+                        users::disabled::equals(false),
+                        users::totp_enabled::equals(true),
+                    ])
+                    .exec()
+                    .await?
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::Unauthorized, "Two-factor authentication is not enabled".into()))?;
+                if claim_totp_code(&db, &user, &login_totp_request.code).await? {
+                    return complete_login(&db, &jwt_config, &user, &client_ip).await
+                }
+                record_login_attempt(&db, &email, &client_ip, false).await?;
+                let retry_after = register_login_failure(&db, &throttle_keys).await?;
+                Ok(LoginResponse::rejected(retry_after))
+            })
+        })
+        .query("requestPasswordReset", |t| {
+            t(|(db, _, mailer, _, _, _), password_reset_request: PasswordResetRequest| async move {
+                let db = db.lock().await;
+                let user = db
+                    .users()
+                    .find_unique(users::email::equals(password_reset_request.email))
+                    .exec()
+                    .await?;
+                // Same response whether or not the account exists, so it can't be used to probe for users
+                if let Some(user) = user.filter(|user| !user.disabled) {
+                    let token = create_password_reset(&db, &user.email).await?;
+                    drop(db);
+                    if let Err(e) = mailer.send(password_reset_mail(&user.email, &token, PASSWORD_RESET_MINUTES)).await {
+                        println!("[ERROR: {}] password reset mail to '{}' failed: {}", Utc::now(), user.email, e);
+                    }
+                }
+                Ok(())
+            })
+        })
+        .query("confirmPasswordReset", |t| {
+            t(|(db, _, _, _, _, _), confirm_request: ConfirmPasswordResetRequest| async move {
+                let db_hash = hash_password(&confirm_request.new_password)?;
+                let db = db.lock().await;
+                // Claim the token in a single conditional update, so it can only be used once
+                let token_hash = hash_token(&confirm_request.token);
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                let claimed = db
+                    .password_reset()
+                    .update_many(
+                        vec![
+                            password_reset::token_hash::equals(token_hash.clone()),
+                            password_reset::used_at::equals(None),
+                            password_reset::expires_at::gt(now),
+                        ],
+                        vec![password_reset::used_at::set(Some(now))]
+                    )
+                    .exec()
+                    .await?;
+                let reset = match db.password_reset().find_unique(password_reset::token_hash::equals(token_hash)).exec().await? {
+                    Some(reset) if claimed == 1 => reset,
+                    _ => return Err(rspc::Error::new(ErrorCode::Forbidden, "Invalid or expired reset token".into())),
+                };
+                db
+                    .users()
+                    .update(
+                        users::email::equals(reset.user_email.clone()),
+                        vec![
+                            users::hash::set(db_hash),
+                            users::must_change_password::set(false),
+                        ]
+                    )
+                    .exec()
+                    .await?;
+                revoke_sessions(&db, &reset.user_email, None).await
+            })
+        })
+        .query("refresh", |t| {
+            t(|(db, jwt_config, _, _, _, _), refresh_request: RefreshRequest| async move {
+                let db = db.lock().await;
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                let session = db
+                    .session()
+                    .find_first(vec![
+                        session::refresh_hash::equals(hash_token(&refresh_request.refresh_token)),
+                        session::revoked::equals(false),
+                        session::expires_at::gt(now),
+                        session::user::is(vec![users::disabled::equals(false)]),
+                    ])
+                    .with(session::user::fetch())
+                    .exec()
+                    .await?
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::Unauthorized, "Invalid refresh token".into()))?;
+                let user = session.user()
+                    .map_err(|e| rspc::Error::new(ErrorCode::InternalServerError, e.to_string()))?;
+                // Rotate: the presented refresh token stops working from here on
+                let refresh_token = gen_token();
+                db
+                    .session()
+                    .update(
+                        session::id::equals(session.id.clone()),
+                        vec![
+                            session::refresh_hash::set(hash_token(&refresh_token)),
+                            session::expires_at::set((Utc::now() + jwt_config.refresh_expiry()).into()),
+                        ]
+                    )
+                    .exec()
+                    .await?;
+                // Role is read from the db again, so changes apply with the next access token
+                let token = gen_jwt(&jwt_config, &user.email, &user.role, &session.id)
+                    .map_err(|e| rspc::Error::new(ErrorCode::InternalServerError, e.to_string()))?;
+                Ok(LoginResponse {token: Some(token), refresh_token: Some(refresh_token), must_change_password: user.must_change_password, retry_after: None, mfa_token: None, must_enroll_totp: totp_required(&user.role) && !user.totp_enabled})
+            })
+        })
+        // authentication: later procedures run with an AuthCtx and need the permission declared in required_permission
+        .middleware(|mw| {
+            mw.middleware(|mw| async move {
+                let (db, jwt_config, mailer, headers, _, _) = mw.ctx.clone();
+                let token = session_token(&headers, jwt_config.cookie_origins()).ok_or_else(|| rspc::Error::new(
+                    ErrorCode::Unauthorized,
+                    "Unauthorized".into(),
+                ))?;
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                let principal = if is_api_key(token) {
+                    api_key_principal(&*db.lock().await, token, now).await?
+                } else {
+                    session_principal(&*db.lock().await, &jwt_config, token, now, &mw.req.path).await?
+                };
+                let permission = required_permission(&mw.req.path, &mw.input).ok_or_else(|| rspc::Error::new(
+                    ErrorCode::InternalServerError,
+                    format!("No permission declared for '{}'", mw.req.path),
+                ))?;
+                if !principal.has_permission(permission) {
+                    return Err(rspc::Error::new(
+                        ErrorCode::Forbidden,
+                        format!("Missing permission {:?}", permission),
+                    ));
+                }
+                // We use `.with_ctx` to switch the context type.
+                Ok(mw.with_ctx(AuthCtx { db, mailer, principal }))
+            })
+        })
+        .query("queries", |t| {
+            t(|AuthCtx { db, .. }, queries_request: QueriesRequest| async move {
                 let db = db.lock().await;
                 db.queries()
                     .find_many(vec![queries::query::starts_with(queries_request.prefix)])
@@ -339,8 +1307,7 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
             })
         })
         .query("all_queries", |t| {
-            t(|(db, _, _), _: ()| async move {
-                //TODO: add admin token validation
+            t(|AuthCtx { db, .. }, _: ()| async move {
                 let db = db.lock().await;
                 db.queries()
                     .find_many(vec![])
@@ -350,179 +1317,1241 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                     .map_err(Into::into)
             })
         })
-        .query("view_node", |t| {
-            t(|(db, _, _), view_node_request: ViewNodeRequest| async move {
-                let view_update = 
-                    if view_node_request.is_hover {
-                        node::wlk_hovers::increment(1)
-                    } else {
-                        node::wlk_clicks::increment(1)
-                    };
+        .query("createInvite", |t| {
+            t(|AuthCtx { db, principal, .. }, create_invite_request: CreateInviteRequest| async move {
+                let valid_days = create_invite_request.valid_days.unwrap_or(DEFAULT_INVITE_DAYS);
+                if !(1..=MAX_INVITE_DAYS).contains(&valid_days) {
+                    return Err(rspc::Error::new(
+                        ErrorCode::BadRequest,
+                        format!("Invites are valid for 1 to {} days", MAX_INVITE_DAYS),
+                    ));
+                }
+                let code = gen_token();
+                let expires_at: DateTime<FixedOffset> = (Utc::now() + chrono::Duration::days(valid_days)).into();
                 let db = db.lock().await;
                 db
-                    .node()
+                    .invite()
+                    .create(
+                        hash_token(&code),
+                        create_invite_request.role,
+                        principal.actor(),
+                        expires_at,
+                        vec![]
+                    )
+                    .exec()
+                    .await?;
+                Ok(CreateInviteResponse {code, expires_at})
+            })
+        })
+        .query("invites", |t| {
+            t(|AuthCtx { db, .. }, _: ()| async move {
+                let db = db.lock().await;
+                db.invite()
+                    .find_many(vec![])
+                    .order_by(invite::created_at::order(Direction::Desc))
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("revokeInvite", |t| {
+            t(|AuthCtx { db, .. }, revoke_invite_request: RevokeInviteRequest| async move {
+                let db = db.lock().await;
+                db
+                    .invite()
+                    .delete(invite::code_hash::equals(revoke_invite_request.code_hash))
+                    .exec()
+                    .await
+                    .map(|_| ())
+                    .map_err(Into::into)
+            })
+        })
+        .query("users", |t| {
+            t(|AuthCtx { db, .. }, _: ()| async move {
+                let db = db.lock().await;
+                Ok(db.users()
+                    .find_many(vec![])
+                    .order_by(users::email::order(Direction::Asc))
+                    .select(user_info::select())
+                    .exec()
+                    .await?
+                    .into_iter()
+                    .map(UserInfo)
+                    .collect::<Vec<UserInfo>>())
+            })
+        })
+        .query("setUserRole", |t| {
+            t(|AuthCtx { db, principal, .. }, set_user_role_request: SetUserRoleRequest| async move {
+                reject_self(&principal, &set_user_role_request.email)?;
+                let db = db.lock().await;
+                db
+                    .users()
                     .update(
-                        node::name::equals(view_node_request.name),
-                        vec![view_update]
+                        users::email::equals(set_user_role_request.email),
+                        vec![users::role::set(set_user_role_request.role)]
                     )
                     .exec()
                     .await
+                    .map(|_| ())
                     .map_err(Into::into)
             })
         })
-        .query("view_article", |t| {
-            t(|(db, _, _), view_article_request: ViewArticleRequest| async move {
-                let view_update = 
-                    if view_article_request.is_hover {
-                        article::wlk_hovers::increment(1)
-                    } else {
-                        article::wlk_clicks::increment(1)
-                    };
+        .query("setUserDisabled", |t| {
+            t(|AuthCtx { db, principal, .. }, set_user_disabled_request: SetUserDisabledRequest| async move {
+                reject_self(&principal, &set_user_disabled_request.email)?;
                 let db = db.lock().await;
                 db
-                    .article()
+                    .users()
                     .update(
-                        article::name::equals(view_article_request.name),
-                        vec![view_update]
+                        users::email::equals(set_user_disabled_request.email.clone()),
+                        vec![users::disabled::set(set_user_disabled_request.disabled)]
                     )
                     .exec()
+                    .await?;
+                if set_user_disabled_request.disabled {
+                    revoke_sessions(&db, &set_user_disabled_request.email, None).await?;
+                }
+                Ok(())
+            })
+        })
+        .query("deleteUser", |t| {
+            t(|AuthCtx { db, principal, .. }, user_request: UserRequest| async move {
+                reject_self(&principal, &user_request.email)?;
+                let db = db.lock().await;
+                // Sessions are deleted by the cascade
+                db
+                    .users()
+                    .delete(users::email::equals(user_request.email))
+                    .exec()
                     .await
+                    .map(|_| ())
                     .map_err(Into::into)
             })
         })
-        .query("register", |t| {
-            t(|(db, _, _), register_request: RegisterRequest| async move {
-                let salt = gen_salt();
-                let db_salt_hash = gen_salt_hash(&salt, &register_request.password);
+        .query("forcePasswordReset", |t| {
+            t(|AuthCtx { db, mailer, principal }, user_request: UserRequest| async move {
+                reject_self(&principal, &user_request.email)?;
                 let db = db.lock().await;
                 db
                     .users()
-                    .create(
-                        register_request.email, // Throws error upon duplicates TODO: check if user exists fist to prevent ddos
-                        db_salt_hash,
-                        Role::Admin, //TODO: this is for testing, remove in production
-                        vec![]
+                    .update(
+                        users::email::equals(user_request.email.clone()),
+                        vec![users::must_change_password::set(true)]
+                    )
+                    .exec()
+                    .await?;
+                revoke_sessions(&db, &user_request.email, None).await?;
+                let token = create_password_reset(&db, &user_request.email).await?;
+                drop(db);
+                mailer
+                    .send(password_reset_mail(&user_request.email, &token, PASSWORD_RESET_MINUTES))
+                    .await
+                    .map_err(|e| rspc::Error::new(ErrorCode::InternalServerError, e.to_string()))
+            })
+        })
+        .query("changePassword", |t| {
+            t(|AuthCtx { db, principal, .. }, change_password_request: ChangePasswordRequest| async move {
+                let claims = session_claims(&principal)?;
+                let db = db.lock().await;
+                let user = db
+                    .users()
+                    .find_unique(users::email::equals(claims.email.clone()))
+                    .exec()
+                    .await?
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, "User not found".into()))?;
+                if verify_password(&change_password_request.current_password, &user.hash)? == PasswordMatch::Invalid {
+                    return Err(rspc::Error::new(ErrorCode::Forbidden, "Current password is wrong".into()));
+                }
+                db
+                    .users()
+                    .update(
+                        users::email::equals(claims.email.clone()),
+                        vec![
+                            users::hash::set(hash_password(&change_password_request.new_password)?),
+                            users::must_change_password::set(false),
+                        ]
                     )
                     .exec()
+                    .await?;
+                // Whoever else knew the old password is signed out, this session stays
+                revoke_sessions(&db, &claims.email, Some(&claims.sid)).await
+            })
+        })
+        .query("loginLockouts", |t| {
+            t(|AuthCtx { db, .. }, _: ()| async move {
+                let db = db.lock().await;
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                db.login_throttle()
+                    .find_many(vec![login_throttle::locked_until::gt(now)])
+                    .order_by(login_throttle::locked_until::order(Direction::Desc))
+                    .exec()
                     .await
                     .map_err(Into::into)
             })
         })
-        .query("login", |t| {
-            t(|(db, _, _), login_request: LoginRequest| async move {
+        .query("loginAttempts", |t| {
+            t(|AuthCtx { db, .. }, login_attempts_request: LoginAttemptsRequest| async move {
                 let db = db.lock().await;
-                if let Some(user) = db.users()
-                    .find_unique(users::email::equals(login_request.email))
+                let mut filter = vec![];
+                if let Some(email) = login_attempts_request.email {
+                    filter.push(login_attempt::email::equals(email));
+                }
+                if let Some(ip) = login_attempts_request.ip {
+                    filter.push(login_attempt::ip::equals(ip));
+                }
+                if login_attempts_request.failed_only {
+                    filter.push(login_attempt::success::equals(false));
+                }
+                db.login_attempt()
+                    .find_many(filter)
+                    .order_by(login_attempt::created_at::order(Direction::Desc))
+                    .take(LOGIN_ATTEMPTS_PAGE_SIZE)
                     .exec()
                     .await
-                    .unwrap()
-                {
-                    // Compares hashes of salt+request_pw to salt+db_pw
-                    if cmp_salt_hash(&login_request.password, &user.hash) {
-                        // Valid pw, generate session token
-                        let token = gen_jwt(&user.email, &user.role).unwrap(); //TODO error handling
-                        return LoginResponse {token: Some(token)}
+                    .map_err(Into::into)
+            })
+        })
+        .query("clearLockout", |t| {
+            t(|AuthCtx { db, .. }, clear_lockout_request: ClearLockoutRequest| async move {
+                let db = db.lock().await;
+                db
+                    .login_throttle()
+                    .delete(login_throttle::key::equals(clear_lockout_request.key))
+                    .exec()
+                    .await
+                    .map(|_| ())
+                    .map_err(Into::into)
+            })
+        })
+        .query("totpEnroll", |t| {
+            t(|AuthCtx { db, principal, .. }, _: ()| async move {
+                let claims = session_claims(&principal)?;
+                let secret = totp::gen_secret();
+                let db = db.lock().await;
+                // Re-enrolling must not silently replace a working factor, that is what resetTotp is for
+                let pending = db
+                    .users()
+                    .update_many(
+                        vec![
+                            users::email::equals(claims.email.clone()),
+                            users::totp_enabled::equals(false),
+                        ],
+                        vec![users::totp_secret::set(Some(secret.clone()))]
+                    )
+                    .exec()
+                    .await?;
+                if pending == 0 {
+                    return Err(rspc::Error::new(ErrorCode::BadRequest, "Two-factor authentication is already enabled".into()));
+                }
+                Ok(TotpEnrollResponse {uri: totp::provisioning_uri(&claims.email, &secret), secret})
+            })
+        })
+        .query("totpConfirm", |t| {
+            t(|AuthCtx { db, principal, .. }, totp_confirm_request: TotpConfirmRequest| async move {
+                let claims = session_claims(&principal)?;
+                let db = db.lock().await;
+                let user = db
+                    .users()
+                    .find_unique(users::email::equals(claims.email.clone()))
+                    .exec()
+                    .await?
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, "User not found".into()))?;
+                let secret = match (&user.totp_secret, user.totp_enabled) {
+                    (Some(secret), false) => secret,
+                    _ => return Err(rspc::Error::new(ErrorCode::BadRequest, "No pending two-factor enrollment, call totpEnroll first".into())),
+                };
+                let step = totp::verify_code(secret, &totp_confirm_request.code, Utc::now().timestamp(), None)
+                    .ok_or_else(|| rspc::Error::new(ErrorCode::BadRequest, "Wrong code".into()))?;
+                db
+                    .users()
+                    .update(
+                        users::email::equals(user.email.clone()),
+                        vec![
+                            users::totp_enabled::set(true),
+                            users::totp_last_step::set(Some(step as i32)),
+                        ]
+                    )
+                    .exec()
+                    .await?;
+                db
+                    .recovery_code()
+                    .delete_many(vec![recovery_code::user_email::equals(user.email.clone())])
+                    .exec()
+                    .await?;
+                let codes = totp::gen_recovery_codes();
+                for code in &codes {
+                    db
+                        .recovery_code()
+                        .create(
+                            hash_token(&totp::normalize_recovery_code(code)),
+                            users::email::equals(user.email.clone()),
+                            vec![]
+                        )
+                        .exec()
+                        .await?;
+                }
+                // Shown once, only the hashes are kept
+                Ok(codes)
+            })
+        })
+        .query("resetTotp", |t| {
+            t(|AuthCtx { db, principal, .. }, user_request: UserRequest| async move {
+                reject_self(&principal, &user_request.email)?;
+                let db = db.lock().await;
+                db
+                    .users()
+                    .update(
+                        users::email::equals(user_request.email.clone()),
+                        vec![
+                            users::totp_secret::set(None),
+                            users::totp_enabled::set(false),
+                            users::totp_last_step::set(None),
+                        ]
+                    )
+                    .exec()
+                    .await?;
+                db
+                    .recovery_code()
+                    .delete_many(vec![recovery_code::user_email::equals(user_request.email.clone())])
+                    .exec()
+                    .await?;
+                // The user has to sign in again and enroll a new authenticator
+                revoke_sessions(&db, &user_request.email, None).await
+            })
+        })
+        .query("auditLog", |t| {
+            t(|AuthCtx { db, .. }, audit_log_request: AuditLogRequest| async move {
+                let db = db.lock().await;
+                let mut filter = vec![];
+                if let Some(actor) = audit_log_request.actor {
+                    filter.push(audit_log::actor::equals(actor));
+                }
+                if let Some(entity) = audit_log_request.entity {
+                    filter.push(audit_log::entity::equals(entity));
+                }
+                if let Some(entity_key) = audit_log_request.entity_key {
+                    filter.push(audit_log::entity_key::equals(entity_key));
+                }
+                if let Some(from) = audit_log_request.from {
+                    filter.push(audit_log::created_at::gte(from));
+                }
+                if let Some(to) = audit_log_request.to {
+                    filter.push(audit_log::created_at::lt(to));
+                }
+                db.audit_log()
+                    .find_many(filter)
+                    .order_by(audit_log::created_at::order(Direction::Desc))
+                    .take(AUDIT_LOG_PAGE_SIZE)
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("adminNodes", |t| {
+            t(|AuthCtx { db, .. }, admin_content_request: AdminContentRequest| async move {
+                let db = db.lock().await;
+                let filter = admin_content_request.status.map(node::status::equals).into_iter().collect();
+                db.node()
+                    .find_many(filter)
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("adminEdges", |t| {
+            t(|AuthCtx { db, .. }, admin_content_request: AdminContentRequest| async move {
+                let db = db.lock().await;
+                let filter = admin_content_request.status.map(edge::status::equals).into_iter().collect();
+                Ok(db.edge()
+                    .find_many(filter)
+                    .include(edge_articles_info::include())
+                    .exec()
+                    .await?
+                    .into_iter()
+                    .map(EdgeWithArticlesInfo)
+                    .collect::<Vec<EdgeWithArticlesInfo>>())
+            })
+        })
+        .query("adminArticles", |t| {
+            t(|AuthCtx { db, .. }, admin_content_request: AdminContentRequest| async move {
+                let db = db.lock().await;
+                let filter = admin_content_request.status.map(article::status::equals).into_iter().collect();
+                db.article()
+                    .find_many(filter)
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("submitForReview", |t| {
+            t(|AuthCtx { db, principal, .. }, submit_for_review_request: SubmitForReviewRequest| async move {
+                let db = db.lock().await;
+                set_publication_status(&db, &principal, submit_for_review_request.content, PublicationStatus::InReview, Some(PublicationStatus::Draft), "SubmitForReview").await
+            })
+        })
+        .query("reviewContent", |t| {
+            t(|AuthCtx { db, principal, .. }, review_content_request: ReviewContentRequest| async move {
+                let db = db.lock().await;
+                let (status, action) = if review_content_request.approve {
+                    (PublicationStatus::Published, "Publish")
+                } else {
+                    (PublicationStatus::Draft, "RejectReview")
+                };
+                // A proposed change to reviewed content is applied or discarded, the content itself stays online either way
+                if review_proposal(&db, &principal, &review_content_request.content, review_content_request.approve, action).await? {
+                    return Ok(())
+                }
+                // Publishers may also publish drafts directly, without a review request
+                set_publication_status(&db, &principal, review_content_request.content, status, None, action).await
+            })
+        })
+        .query("revisions", |t| {
+            t(|AuthCtx { db, .. }, revisions_request: RevisionsRequest| async move {
+                let db = db.lock().await;
+                db.revision()
+                    .find_many(vec![
+                        revision::entity::equals(revisions_request.entity.to_string()),
+                        revision::entity_key::equals(revisions_request.entity_key),
+                    ])
+                    .order_by(revision::number::order(Direction::Desc))
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("pendingRevisions", |t| {
+            t(|AuthCtx { db, .. }, _: ()| async move {
+                let db = db.lock().await;
+                db.revision()
+                    .find_many(vec![revision::pending::equals(true)])
+                    .order_by(revision::created_at::order(Direction::Asc))
+                    .exec()
+                    .await
+                    .map_err(Into::into)
+            })
+        })
+        .query("revisionDiff", |t| {
+            t(|AuthCtx { db, .. }, revision_diff_request: RevisionDiffRequest| async move {
+                let db = db.lock().await;
+                let from = find_revision(&db, revision_diff_request.entity, &revision_diff_request.entity_key, revision_diff_request.from).await?;
+                let to = find_revision(&db, revision_diff_request.entity, &revision_diff_request.entity_key, revision_diff_request.to).await?;
+                Ok(json_diff(&from.data, &to.data))
+            })
+        })
+        .query("restoreRevision", |t| {
+            t(|AuthCtx { db, principal, .. }, restore_revision_request: RestoreRevisionRequest| async move {
+                let db = db.lock().await;
+                let entity = restore_revision_request.entity;
+                let entity_key = restore_revision_request.entity_key;
+                let revision = find_revision(&db, entity, &entity_key, restore_revision_request.number).await?;
+                if revision.deleted {
+                    return Err(rspc::Error::new(ErrorCode::BadRequest, "This revision is a deletion, restore an earlier one".into()));
+                }
+                if revision.pending {
+                    return Err(rspc::Error::new(ErrorCode::BadRequest, "This revision is a pending change, review it with reviewContent".into()));
+                }
+                let mut snapshot_data = current_snapshot(&db, entity, &entity_key, revision.data.clone()).await?;
+                // Restoring is a write like any other, without the publish permission it has to be reviewed again
+                let requested_status = snapshot_data
+                    .get("status")
+                    .and_then(|status| serde_json::from_value(status.clone()).ok())
+                    .unwrap_or(PublicationStatus::Published); // Revisions from before the review workflow
+                let publish_at = snapshot_data
+                    .get("publish_at")
+                    .and_then(|publish_at| serde_json::from_value(publish_at.clone()).ok());
+                snapshot_data["status"] = serde_json::to_value(write_status(&principal, requested_status, publish_at, None)).unwrap_or_default();
+                let invalid_snapshot = |e: serde_json::Error| rspc::Error::new(ErrorCode::InternalServerError, format!("Revision {} can't be restored: {}", revision.number, e));
+                // Restoring is a change of its own, so it gets a new revision instead of rewinding the history
+                match entity {
+                    RevisionEntity::Node => {
+                        let snapshot: node::Data = serde_json::from_value(snapshot_data).map_err(invalid_snapshot)?;
+                        let before = db.node().find_unique(node::name::equals(entity_key.clone())).exec().await?;
+                        if let Some(current) = before.as_ref().filter(|before| needs_proposal(&principal, before.status)) {
+                            propose_change(&db, &principal, entity, entity_key, current, &node::Data {status: current.status, ..snapshot}).await?;
+                        } else {
+                            let after = restore_node(&db, snapshot).await?;
+                            record_revisioned_change(&db, &principal.actor(), "Restore", &entity.to_string(), entity_key, before.as_ref(), Some(&after)).await?;
+                        }
+                    }
+                    RevisionEntity::Article => {
+                        let snapshot: article::Data = serde_json::from_value(snapshot_data).map_err(invalid_snapshot)?;
+                        let before = db.article().find_unique(article::name::equals(entity_key.clone())).exec().await?;
+                        if let Some(current) = before.as_ref().filter(|before| needs_proposal(&principal, before.status)) {
+                            propose_change(&db, &principal, entity, entity_key, current, &article::Data {status: current.status, ..snapshot}).await?;
+                        } else {
+                            let after = restore_article(&db, snapshot).await?;
+                            record_revisioned_change(&db, &principal.actor(), "Restore", &entity.to_string(), entity_key, before.as_ref(), Some(&after)).await?;
+                        }
+                    }
+                }
+                Ok(())
+            })
+        })
+        .query("createApiKey", |t| {
+            t(|AuthCtx { db, principal, .. }, create_api_key_request: CreateApiKeyRequest| async move {
+                if create_api_key_request.name.trim().is_empty() || create_api_key_request.scopes.is_empty() {
+                    return Err(rspc::Error::new(
+                        ErrorCode::BadRequest,
+                        "API keys need a name and at least one scope".into(),
+                    ));
+                }
+                let expires_at: Option<DateTime<FixedOffset>> = match create_api_key_request.valid_days {
+                    Some(valid_days) if valid_days < 1 => return Err(rspc::Error::new(
+                        ErrorCode::BadRequest,
+                        "API keys are valid for at least one day".into(),
+                    )),
+                    Some(valid_days) => Some((Utc::now() + chrono::Duration::days(valid_days)).into()),
+                    None => None,
+                };
+                let key = gen_api_key();
+                let prefix = api_key_display_prefix(&key);
+                let db = db.lock().await;
+                db
+                    .api_key()
+                    .create(
+                        create_api_key_request.name,
+                        prefix.clone(),
+                        hash_token(&key),
+                        principal.actor(),
+                        vec![
+                            api_key::scopes::set(create_api_key_request.scopes),
+                            api_key::expires_at::set(expires_at),
+                        ]
+                    )
+                    .exec()
+                    .await?;
+                Ok(CreateApiKeyResponse {key, prefix, expires_at})
+            })
+        })
+        .query("apiKeys", |t| {
+            t(|AuthCtx { db, .. }, _: ()| async move {
+                let db = db.lock().await;
+                db.api_key()
+                    .find_many(vec![])
+                    .order_by(api_key::created_at::order(Direction::Desc))
+                    .select(api_key_info::select())
+                    .exec()
+                    .await
+                    .map(|keys| keys.into_iter().map(ApiKeyInfo).collect::<Vec<_>>())
+                    .map_err(Into::into)
+            })
+        })
+        .query("revokeApiKey", |t| {
+            t(|AuthCtx { db, .. }, revoke_api_key_request: RevokeApiKeyRequest| async move {
+                let db = db.lock().await;
+                let now: DateTime<FixedOffset> = Utc::now().into();
+                // Kept instead of deleted, so the overview still shows who created and last used it
+                db
+                    .api_key()
+                    .update(
+                        api_key::id::equals(revoke_api_key_request.id),
+                        vec![api_key::revoked_at::set(Some(now))]
+                    )
+                    .exec()
+                    .await
+                    .map(|_| ())
+                    .map_err(Into::into)
+            })
+        })
+        .query("logout", |t| {
+            t(|AuthCtx { db, principal, .. }, _: ()| async move {
+                let claims = session_claims(&principal)?;
+                let db = db.lock().await;
+                db
+                    .session()
+                    .update(
+                        session::id::equals(claims.sid.clone()),
+                        vec![session::revoked::set(true)]
+                    )
+                    .exec()
+                    .await
+                    .map(|_| ())
+                    .map_err(Into::into)
+            })
+        })
+        .query("logoutEverywhere", |t| {
+            t(|AuthCtx { db, principal, .. }, _: ()| async move {
+                let claims = session_claims(&principal)?;
+                let db = db.lock().await;
+                revoke_sessions(&db, &claims.email, None).await
+            })
+        })
+        .query("deleteAllData", |t| {
+            t(|AuthCtx { db, principal, .. }, _: ()| async move {
+                let empty_string = "".to_string(); //TODO: find cleaner way to select all
+                let db = db.lock().await;
+
+                //Delete all articles
+                let deleted_articles = db //TODO: do proper error processing
+                .article()
+                .delete_many(vec![
+                    article::edge_source_id::not(empty_string.clone())
+                ])
+                .exec()
+                .await;
+                
+                //Delete all edges
+                let deleted_edges = db
+                .edge()
+                .delete_many(vec![
+                    edge::source_id::not(empty_string.clone())
+                ])
+                .exec()
+                .await;
+
+                //Delete all nodes
+                let db_response = db
+                .node()
+                .delete_many(vec![
+                    node::name::not(empty_string.clone())
+                ])
+                .exec()
+                .await;
+
+                //Delete all categories
+                let deleted_categories = db
+                .category()
+                .delete_many(vec![
+                    category::name::not(empty_string.clone())
+                ])
+                .exec()
+                .await;
+
+                //Delete all disciplines
+                let deleted_disciplines = db
+                .discipline()
+                .delete_many(vec![
+                    discipline::name::not(empty_string)
+                ])
+                .exec()
+                .await;
+
+                // Row counts only, the rows themselves would make the record as large as the dataset
+                let deleted = serde_json::json!({
+                    "articles": deleted_articles.unwrap_or_default(),
+                    "edges": deleted_edges.unwrap_or_default(),
+                    "nodes": db_response.as_ref().map_or(0, |count| *count),
+                    "categories": deleted_categories.unwrap_or_default(),
+                    "disciplines": deleted_disciplines.unwrap_or_default(),
+                });
+                let audit_response = db
+                    .audit_log()
+                    .create(principal.actor(), "DeleteAllData".to_string(), "All".to_string(), "*".to_string(), deleted, vec![])
+                    .exec()
+                    .await;
+                ModResponse {error: db_response.err().or(audit_response.err()).map(|e| e.to_string())}
+            })
+        })
+        .query("modNodeTranslation", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_node_translation_request: ModNodeTranslationRequest| async move {
+                let db = db.lock().await;
+                let data = mod_node_translation_request.data;
+                let entity_key = format!("{}:{:?}", data.node_name, data.locale);
+                let unique = || node_translation::node_name_locale(data.node_name.clone(), data.locale);
+                let before = db.node_translation().find_unique(unique()).exec().await.ok().flatten();
+                let action = mod_node_translation_request.op.to_string();
+                // Translations are identified by entity and locale, so create and update both come down to an upsert
+                let db_response = match mod_node_translation_request.op {
+                    ModType::Delete => {
+                        db
+                        .node_translation()
+                        .delete(unique())
+                        .exec()
+                        .await
+                    },
+                    _ => {
+                        db
+                        .node_translation()
+                        .upsert(unique(),
+                            (node::name::equals(data.node_name.clone()), data.locale, data.display_name.clone(), vec![node_translation::teaser_text::set(data.teaser_text.clone())]),
+                            vec![node_translation::display_name::set(data.display_name.clone()), node_translation::teaser_text::set(data.teaser_text.clone())]
+                        )
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_node_translation_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "NodeTranslation", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modArticleTranslation", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_article_translation_request: ModArticleTranslationRequest| async move {
+                let db = db.lock().await;
+                let data = mod_article_translation_request.data;
+                let entity_key = format!("{}:{:?}", data.article_name, data.locale);
+                let unique = || article_translation::article_name_locale(data.article_name.clone(), data.locale);
+                let before = db.article_translation().find_unique(unique()).exec().await.ok().flatten();
+                let action = mod_article_translation_request.op.to_string();
+                // Translations are identified by entity and locale, so create and update both come down to an upsert
+                let db_response = match mod_article_translation_request.op {
+                    ModType::Delete => {
+                        db
+                        .article_translation()
+                        .delete(unique())
+                        .exec()
+                        .await
+                    },
+                    _ => {
+                        db
+                        .article_translation()
+                        .upsert(unique(),
+                            (article::name::equals(data.article_name.clone()), data.locale, data.display_name.clone(), vec![article_translation::teaser_text::set(data.teaser_text.clone())]),
+                            vec![article_translation::display_name::set(data.display_name.clone()), article_translation::teaser_text::set(data.teaser_text.clone())]
+                        )
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_article_translation_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "ArticleTranslation", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modCategoryTranslation", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_category_translation_request: ModCategoryTranslationRequest| async move {
+                let db = db.lock().await;
+                let data = mod_category_translation_request.data;
+                let entity_key = format!("{}:{:?}", data.category_name, data.locale);
+                let unique = || category_translation::category_name_locale(data.category_name.clone(), data.locale);
+                let before = db.category_translation().find_unique(unique()).exec().await.ok().flatten();
+                let action = mod_category_translation_request.op.to_string();
+                // Translations are identified by entity and locale, so create and update both come down to an upsert
+                let db_response = match mod_category_translation_request.op {
+                    ModType::Delete => {
+                        db
+                        .category_translation()
+                        .delete(unique())
+                        .exec()
+                        .await
+                    },
+                    _ => {
+                        db
+                        .category_translation()
+                        .upsert(unique(),
+                            (category::name::equals(data.category_name.clone()), data.locale, data.display_name.clone(), vec![]),
+                            vec![category_translation::display_name::set(data.display_name.clone())]
+                        )
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_category_translation_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "CategoryTranslation", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modDisciplineTranslation", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_discipline_translation_request: ModDisciplineTranslationRequest| async move {
+                let db = db.lock().await;
+                let data = mod_discipline_translation_request.data;
+                let entity_key = format!("{}:{:?}", data.discipline_name, data.locale);
+                let unique = || discipline_translation::discipline_name_locale(data.discipline_name.clone(), data.locale);
+                let before = db.discipline_translation().find_unique(unique()).exec().await.ok().flatten();
+                let action = mod_discipline_translation_request.op.to_string();
+                // Translations are identified by entity and locale, so create and update both come down to an upsert
+                let db_response = match mod_discipline_translation_request.op {
+                    ModType::Delete => {
+                        db
+                        .discipline_translation()
+                        .delete(unique())
+                        .exec()
+                        .await
+                    },
+                    _ => {
+                        db
+                        .discipline_translation()
+                        .upsert(unique(),
+                            (discipline::name::equals(data.discipline_name.clone()), data.locale, data.display_name.clone(), vec![]),
+                            vec![discipline_translation::display_name::set(data.display_name.clone())]
+                        )
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_discipline_translation_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "DisciplineTranslation", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modNodeAlias", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_node_alias_request: ModNodeAliasRequest| async move {
+                let db = db.lock().await;
+                let data = mod_node_alias_request.data;
+                let alias = data.alias.trim().to_string();
+                let normalized = normalize_alias(&alias);
+                if normalized.is_empty() {
+                    return ModResponse {error: Some("The alias is empty".to_string())};
+                }
+                // Names take precedence, an alias equal to a node name would never be found
+                if !matches!(mod_node_alias_request.op, ModType::Delete) {
+                    match db.node().find_unique(node::name::equals(alias.clone())).exec().await {
+                        Ok(Some(node)) => return ModResponse {error: Some(format!("'{}' is already the name of a node", node.name))},
+                        Ok(None) => {},
+                        Err(e) => return ModResponse {error: Some(e.to_string())},
+                    }
+                }
+                let entity_key = normalized.clone();
+                let unique = || node_alias::normalized::equals(normalized.clone());
+                let before = db.node_alias().find_unique(unique()).exec().await.ok().flatten();
+                let action = mod_node_alias_request.op.to_string();
+                let update_param = vec![
+                    node_alias::alias::set(alias.clone()),
+                    node_alias::node_name::set(data.node_name.clone()),
+                ];
+                let create_param = (normalized.clone(), alias, node::name::equals(data.node_name), vec![]);
+                let db_response = match mod_node_alias_request.op {
+                    ModType::Create => {
+                        db
+                        .node_alias()
+                        .create(create_param.0,
+                            create_param.1,
+                            create_param.2,
+                            create_param.3
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Update => {
+                        db
+                        .node_alias()
+                        .update(unique(), update_param)
+                        .exec()
+                        .await
+                    },
+                    ModType::Upsert => {
+                        db
+                        .node_alias()
+                        .upsert(unique(),
+                            create_param,
+                            update_param
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Delete => {
+                        db
+                        .node_alias()
+                        .delete(unique())
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_node_alias_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "NodeAlias", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modTag", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_tag_request: ModTagRequest| async move {
+                let db = db.lock().await;
+                let data = mod_tag_request.data;
+                let name = data.name.trim().to_string();
+                if name.is_empty() {
+                    return ModResponse {error: Some("The tag needs a name".to_string())};
+                }
+                let entity_key = data.id.to_string();
+                let before = match mod_tag_request.op {
+                    ModType::Create => None,
+                    _ => db.tag().find_unique(tag::id::equals(data.id)).exec().await.ok().flatten(),
+                };
+                let action = mod_tag_request.op.to_string();
+                let db_response = match mod_tag_request.op {
+                    ModType::Create => {
+                        db
+                        .tag()
+                        .create(name, vec![])
+                        .exec()
+                        .await
+                    },
+                    ModType::Update => {
+                        db
+                        .tag()
+                        .update(tag::id::equals(data.id), vec![tag::name::set(name)])
+                        .exec()
+                        .await
+                    },
+                    ModType::Upsert => {
+                        db
+                        .tag()
+                        .upsert(tag::id::equals(data.id),
+                            (name.clone(), vec![]),
+                            vec![tag::name::set(name)]
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Delete => {
+                        db
+                        .tag()
+                        .delete(tag::id::equals(data.id))
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let entity_key = if matches!(mod_tag_request.op, ModType::Create) { row.id.to_string() } else { entity_key };
+                        let after = (!matches!(mod_tag_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "Tag", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("setNodeTags", |t| {
+            t(|AuthCtx { db, principal, .. }, set_node_tags_request: SetNodeTagsRequest| async move {
+                let db = db.lock().await;
+                let node_name = set_node_tags_request.node_name;
+                let before = match db.node().find_unique(node::name::equals(node_name.clone())).with(node::tags::fetch(vec![])).exec().await {
+                    Ok(Some(node)) => node.tags.unwrap_or_default(),
+                    Ok(None) => return ModResponse {error: Some(format!("Node '{}' not found", node_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let db_response = db
+                    .node()
+                    .update(
+                        node::name::equals(node_name.clone()),
+                        vec![
+                            node::tags::disconnect(before.iter().map(|tag| tag::id::equals(tag.id)).collect()),
+                            node::tags::connect(set_node_tags_request.tag_ids.into_iter().map(tag::id::equals).collect()),
+                        ]
+                    )
+                    .with(node::tags::fetch(vec![]))
+                    .exec()
+                    .await;
+                let error = match db_response {
+                    Ok(node) => {
+                        let tag_names = |tags: &[tag::Data]| serde_json::json!({"tags": tags.iter().map(|tag| tag.name.clone()).collect::<Vec<_>>()});
+                        let after = tag_names(node.tags.as_deref().unwrap_or_default());
+                        record_audit(&db, &principal.actor(), "SetTags", "Node", node_name, Some(&tag_names(&before)), Some(&after)).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("setArticleTags", |t| {
+            t(|AuthCtx { db, principal, .. }, set_article_tags_request: SetArticleTagsRequest| async move {
+                let db = db.lock().await;
+                let article_name = set_article_tags_request.article_name;
+                let before = match db.article().find_unique(article::name::equals(article_name.clone())).with(article::tags::fetch(vec![])).exec().await {
+                    Ok(Some(article)) => article.tags.unwrap_or_default(),
+                    Ok(None) => return ModResponse {error: Some(format!("Article '{}' not found", article_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let db_response = db
+                    .article()
+                    .update(
+                        article::name::equals(article_name.clone()),
+                        vec![
+                            article::tags::disconnect(before.iter().map(|tag| tag::id::equals(tag.id)).collect()),
+                            article::tags::connect(set_article_tags_request.tag_ids.into_iter().map(tag::id::equals).collect()),
+                        ]
+                    )
+                    .with(article::tags::fetch(vec![]))
+                    .exec()
+                    .await;
+                let error = match db_response {
+                    Ok(article) => {
+                        let tag_names = |tags: &[tag::Data]| serde_json::json!({"tags": tags.iter().map(|tag| tag.name.clone()).collect::<Vec<_>>()});
+                        let after = tag_names(article.tags.as_deref().unwrap_or_default());
+                        record_audit(&db, &principal.actor(), "SetTags", "Article", article_name, Some(&tag_names(&before)), Some(&after)).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modAuthor", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_author_request: ModAuthorRequest| async move {
+                let db = db.lock().await;
+                let data = mod_author_request.data;
+                let name = data.name.trim().to_string();
+                if name.is_empty() {
+                    return ModResponse {error: Some("The author needs a name".to_string())};
+                }
+                if matches!(&data.profile_url, Some(url) if !url.starts_with("https://") && !url.starts_with("http://")) {
+                    return ModResponse {error: Some("Profiles need an http or https URL".to_string())};
+                }
+                let entity_key = data.id.to_string();
+                let before = match mod_author_request.op {
+                    ModType::Create => None,
+                    _ => db.author().find_unique(author::id::equals(data.id)).exec().await.ok().flatten(),
+                };
+                let action = mod_author_request.op.to_string();
+                let update_param = (
+                    author::id::equals(data.id),
+                    vec![
+                        author::name::set(name.clone()),
+                        author::affiliation::set(data.affiliation.clone()),
+                        author::discipline_id::set(data.discipline_id.clone()),
+                        author::bio::set(data.bio.clone()),
+                        author::profile_url::set(data.profile_url.clone()),
+                    ]
+                );
+                let create_param = (
+                    name,
+                    vec![
+                        author::affiliation::set(data.affiliation),
+                        author::discipline_id::set(data.discipline_id),
+                        author::bio::set(data.bio),
+                        author::profile_url::set(data.profile_url),
+                    ]
+                );
+                let db_response = match mod_author_request.op {
+                    ModType::Create => {
+                        db
+                        .author()
+                        .create(create_param.0,
+                            create_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Update => {
+                        db
+                        .author()
+                        .update( update_param.0,
+                            update_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Upsert => {
+                        db
+                        .author()
+                        .upsert( update_param.0,
+                            create_param,
+                            update_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Delete => {
+                        db
+                        .author()
+                        .delete(update_param.0)
+                        .exec()
+                        .await
+                    }
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let entity_key = if matches!(mod_author_request.op, ModType::Create) { row.id.to_string() } else { entity_key };
+                        let after = (!matches!(mod_author_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "Author", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("setNodeAuthors", |t| {
+            t(|AuthCtx { db, principal, .. }, set_node_authors_request: SetNodeAuthorsRequest| async move {
+                let db = db.lock().await;
+                let node_name = set_node_authors_request.node_name;
+                let before = match db.node().find_unique(node::name::equals(node_name.clone())).with(node::authors::fetch(vec![])).exec().await {
+                    Ok(Some(node)) => node.authors.unwrap_or_default(),
+                    Ok(None) => return ModResponse {error: Some(format!("Node '{}' not found", node_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let db_response = db
+                    .node()
+                    .update(
+                        node::name::equals(node_name.clone()),
+                        vec![
+                            node::authors::disconnect(before.iter().map(|author| author::id::equals(author.id)).collect()),
+                            node::authors::connect(set_node_authors_request.author_ids.into_iter().map(author::id::equals).collect()),
+                        ]
+                    )
+                    .with(node::authors::fetch(vec![]))
+                    .exec()
+                    .await;
+                let error = match db_response {
+                    Ok(node) => {
+                        let author_names = |authors: &[author::Data]| serde_json::json!({"authors": authors.iter().map(|author| author.name.clone()).collect::<Vec<_>>()});
+                        let after = author_names(node.authors.as_deref().unwrap_or_default());
+                        record_audit(&db, &principal.actor(), "SetAuthors", "Node", node_name, Some(&author_names(&before)), Some(&after)).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("setArticleAuthors", |t| {
+            t(|AuthCtx { db, principal, .. }, set_article_authors_request: SetArticleAuthorsRequest| async move {
+                let db = db.lock().await;
+                let article_name = set_article_authors_request.article_name;
+                let before = match db.article().find_unique(article::name::equals(article_name.clone())).with(article::authors::fetch(vec![])).exec().await {
+                    Ok(Some(article)) => article.authors.unwrap_or_default(),
+                    Ok(None) => return ModResponse {error: Some(format!("Article '{}' not found", article_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let db_response = db
+                    .article()
+                    .update(
+                        article::name::equals(article_name.clone()),
+                        vec![
+                            article::authors::disconnect(before.iter().map(|author| author::id::equals(author.id)).collect()),
+                            article::authors::connect(set_article_authors_request.author_ids.into_iter().map(author::id::equals).collect()),
+                        ]
+                    )
+                    .with(article::authors::fetch(vec![]))
+                    .exec()
+                    .await;
+                let error = match db_response {
+                    Ok(article) => {
+                        let author_names = |authors: &[author::Data]| serde_json::json!({"authors": authors.iter().map(|author| author.name.clone()).collect::<Vec<_>>()});
+                        let after = author_names(article.authors.as_deref().unwrap_or_default());
+                        record_audit(&db, &principal.actor(), "SetAuthors", "Article", article_name, Some(&author_names(&before)), Some(&after)).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
+            })
+        })
+        .query("modRelatedLink", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_related_link_request: ModRelatedLinkRequest| async move {
+                let db = db.lock().await;
+                let data = mod_related_link_request.data;
+                if data.node_name.is_some() == data.article_name.is_some() {
+                    return ModResponse {error: Some("A link belongs to either a node or an article".to_string())};
+                }
+                // Links are rendered as anchors, other schemes like javascript: must not get in
+                if !data.url.starts_with("https://") && !data.url.starts_with("http://") {
+                    return ModResponse {error: Some("Links need an http or https URL".to_string())};
+                }
+                let entity_key = data.id.to_string();
+                let before = match mod_related_link_request.op {
+                    ModType::Create => None,
+                    _ => db.related_link().find_unique(related_link::id::equals(data.id)).exec().await.ok().flatten(),
+                };
+                let action = mod_related_link_request.op.to_string();
+                let owner = match data.node_name.clone() {
+                    Some(node_name) => related_link::node::connect(node::name::equals(node_name)),
+                    None => related_link::article::connect(article::name::equals(data.article_name.clone().unwrap_or_default())),
+                };
+                let update_param = (
+                    related_link::id::equals(data.id),
+                    vec![
+                        related_link::url::set(data.url.clone()),
+                        related_link::title::set(data.title.clone()),
+                        related_link::kind::set(data.kind),
+                        related_link::position::set(data.position),
+                        related_link::node_name::set(data.node_name.clone()),
+                        related_link::article_name::set(data.article_name.clone()),
+                    ]
+                );
+                let create_param = (
+                    data.url,
+                    vec![
+                        related_link::title::set(data.title),
+                        related_link::kind::set(data.kind),
+                        related_link::position::set(data.position),
+                        owner,
+                    ]
+                );
+                let db_response = match mod_related_link_request.op {
+                    ModType::Create => {
+                        db
+                        .related_link()
+                        .create(create_param.0,
+                            create_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Update => {
+                        db
+                        .related_link()
+                        .update( update_param.0,
+                            update_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Upsert => {
+                        db
+                        .related_link()
+                        .upsert( update_param.0,
+                            create_param,
+                            update_param.1
+                        )
+                        .exec()
+                        .await
+                    },
+                    ModType::Delete => {
+                        db
+                        .related_link()
+                        .delete(update_param.0)
+                        .exec()
+                        .await
                     }
-                } 
-                LoginResponse {token: None}
-            })
-        })
-        // authentication: later requests need admin permissions TODO: wont work right now because 
-        /*.middleware(|mw| {
-            mw.middleware(|mw| async move {
-                let headers = mw.ctx.1.clone();
-                match headers.get(COOKIE_SESSION_KEY) {
-                    Some(ref session) => {
-                        match decode_jwt(session.to_str().unwrap_or("wrong_token")) { //TODO: could be cleaner
-                            Ok(_claims) => Ok(mw),
-                            Err(err) => Err(rspc::Error::new(
-                                ErrorCode::Unauthorized,
-                                err.to_string()
-                            ))
-                        }
-                        // We use `.with_ctx` to switch the context type.
-                        //Ok(mw.with_ctx(AuthenticatedCtx { user }))
+                };
+                let error = match db_response {
+                    Ok(row) => {
+                        let entity_key = if matches!(mod_related_link_request.op, ModType::Create) { row.id.to_string() } else { entity_key };
+                        let after = (!matches!(mod_related_link_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "RelatedLink", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
                     }
-                    None => Err(rspc::Error::new(
-                        ErrorCode::Unauthorized,
-                        "Unauthorized".into(),
-                    )),
-                }
-            })
-        }) */
-        .query("deleteAllData", |t| {
-            t(|(db, _, _), delete_all_data_request: DeleteAllDataRequest| async move {
-                if !check_admin(&delete_all_data_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
-                }
-                let empty_string = "".to_string(); //TODO: find cleaner way to select all
-                let db = db.lock().await;
-
-                //Delete all articles
-                let _ = db //TODO: do proper error processing
-                .article()
-                .delete_many(vec![
-                    article::edge_source_id::not(empty_string.clone())
-                ])
-                .exec()
-                .await;
-                
-                //Delete all edges
-                let _ = db
-                .edge()
-                .delete_many(vec![
-                    edge::source_id::not(empty_string.clone())
-                ])
-                .exec()
-                .await;
-
-                //Delete all nodes
-                let db_response = db
-                .node()
-                .delete_many(vec![
-                    node::name::not(empty_string.clone())
-                ])
-                .exec()
-                .await;
-
-                //Delete all categories
-                let _ = db
-                .category()
-                .delete_many(vec![
-                    category::name::not(empty_string.clone())
-                ])
-                .exec()
-                .await;
-
-                //Delete all disciplines
-                let _ = db
-                .discipline()
-                .delete_many(vec![
-                    discipline::name::not(empty_string)
-                ])
-                .exec()
-                .await;
-
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
         .query("modNode", |t| {
-            t(|(db, _, _), mod_node_request: ModNodeRequest| async move {
-                if !check_admin(&mod_node_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
-                }
+            t(|AuthCtx { db, principal, .. }, mod_node_request: ModNodeRequest| async move {
                 let db = db.lock().await;
                 let data = mod_node_request.data;
+                let entity_key = data.name.clone();
+                let before = db.node().find_unique(node::name::equals(data.name.clone())).exec().await.ok().flatten();
+                if let Some(current) = before.as_ref().filter(|before| matches!(mod_node_request.op, ModType::Update | ModType::Upsert) && needs_proposal(&principal, before.status)) {
+                    // The published node stays online, the change waits for reviewContent
+                    let proposed = node::Data {status: current.status, ..data};
+                    let error = propose_change(&db, &principal, RevisionEntity::Node, entity_key, current, &proposed).await.err().map(|e| e.to_string());
+                    return ModResponse {error};
+                }
+                let status = write_status(&principal, data.status, data.publish_at, before.as_ref().map(|before| before.status));
+                let action = mod_node_request.op.to_string();
                 let update_param = (
                     node::name::equals(data.name.clone()),
                     vec![
                         node::category_id::set(data.category_id.clone()),
-                        node::author::set(data.author.clone()),
                         node::teaser_text::set(data.teaser_text.clone()),
                         node::views::set(data.views),
                         node::wlk_hovers::set(data.wlk_hovers),
                         node::wlk_clicks::set(data.wlk_clicks),
-                        node::read_time::set(data.read_time.clone()),
+                        node::read_time_minutes::set(data.read_time_minutes),
+                        node::status::set(status),
+                        node::publish_at::set(data.publish_at),
+                        node::unpublish_at::set(data.unpublish_at),
                     ]
                 );
                 let create_param = (
@@ -530,12 +2559,14 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                     data.url,
                     category::name::equals(data.category_id), 
                     vec![
-                        node::author::set(data.author),
                         node::teaser_text::set(data.teaser_text),
                         node::views::set(data.views),
                         node::wlk_hovers::set(data.wlk_hovers),
                         node::wlk_clicks::set(data.wlk_clicks),
-                        node::read_time::set(data.read_time),
+                        node::read_time_minutes::set(data.read_time_minutes),
+                        node::status::set(status),
+                        node::publish_at::set(data.publish_at),
+                        node::unpublish_at::set(data.unpublish_at),
                     ]
                 );
                 let db_response = match mod_node_request.op {
@@ -577,16 +2608,23 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                         .await
                     }
                 };
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_node_request.op, ModType::Delete)).then_some(row);
+                        record_revisioned_change(&db, &principal.actor(), &action, &RevisionEntity::Node.to_string(), entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
         .query("modCategory", |t| {
-            t(|(db, _, _), mod_category_request: ModCategoryRequest| async move {
-                if !check_admin(&mod_category_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
-                }
+            t(|AuthCtx { db, principal, .. }, mod_category_request: ModCategoryRequest| async move {
                 let db = db.lock().await;
                 let data = mod_category_request.data;
+                let entity_key = data.name.clone();
+                let before = db.category().find_unique(category::name::equals(data.name.clone())).exec().await.ok().flatten();
+                let action = mod_category_request.op.to_string();
                 let update_param = (
                     category::name::equals(data.name.clone()),
                     vec![]
@@ -632,16 +2670,23 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                         .await
                     }
                 };
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_category_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "Category", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
         .query("modDiscipline", |t| {
-            t(|(db, _, _), mod_discipline_request: ModDisciplineRequest| async move {
-                if !check_admin(&mod_discipline_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
-                }
+            t(|AuthCtx { db, principal, .. }, mod_discipline_request: ModDisciplineRequest| async move {
                 let db = db.lock().await;
                 let data = mod_discipline_request.data;
+                let entity_key = data.name.clone();
+                let before = db.discipline().find_unique(discipline::name::equals(data.name.clone())).exec().await.ok().flatten();
+                let action = mod_discipline_request.op.to_string();
                 let update_param = (
                     discipline::name::equals(data.name.clone()),
                     vec![]
@@ -687,24 +2732,163 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                         .await
                     }
                 };
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_discipline_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "Discipline", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
-        .query("modEdge", |t| {
-            t(|(db, _, _), mod_edge_request: ModEdgeRequest| async move {
-                if !check_admin(&mod_edge_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
+        .query("renameNode", |t| {
+            t(|AuthCtx { db, principal, .. }, rename_node_request: RenameNodeRequest| async move {
+                let db = db.lock().await;
+                let old_name = rename_node_request.name;
+                let new_name = rename_node_request.new_name.trim().to_string();
+                if new_name.is_empty() || new_name == old_name {
+                    return ModResponse {error: Some("The new name has to differ from the old one".to_string())};
+                }
+                let before = match db.node().find_unique(node::name::equals(old_name.clone())).exec().await {
+                    Ok(Some(node)) => node,
+                    Ok(None) => return ModResponse {error: Some(format!("Node '{}' not found", old_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                // A new name that resolves to another node (by name, alias or redirect) would make that one unreachable
+                match resolve_node_name(&db, &new_name).await {
+                    Ok(Some(owner)) if owner != old_name => return ModResponse {error: Some(format!("'{}' already refers to the node '{}'", new_name, owner))},
+                    Ok(_) => {},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                }
+                // The revision history moves along, it can't be merged with the one of a former node with the new name
+                match db.revision().count(vec![revision::entity::equals(RevisionEntity::Node.to_string()), revision::entity_key::equals(new_name.clone())]).exec().await {
+                    Ok(0) => {},
+                    Ok(_) => return ModResponse {error: Some(format!("A deleted node named '{}' still has revisions", new_name))},
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                }
+                let edges = match db.edge().find_many(vec![or![edge::source_id::equals(old_name.clone()), edge::target_id::equals(old_name.clone())]]).exec().await {
+                    Ok(edges) => edges,
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let articles = match db.article().find_many(vec![or![article::edge_source_id::equals(old_name.clone()), article::edge_target_id::equals(old_name.clone())]]).exec().await {
+                    Ok(articles) => articles,
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                // The rename changes the public URLs of the node, its edges and their articles at once, there is no proposal
+                // for it like for other changes, so without the publish permission it is limited to unpublished content
+                let published = std::iter::once(before.status)
+                    .chain(edges.iter().map(|edge| edge.status))
+                    .chain(articles.iter().map(|article| article.status))
+                    .any(|status| needs_proposal(&principal, status));
+                if published {
+                    return ModResponse {error: Some(format!("'{}' or its edges and articles are published, renaming it needs review by a publisher", old_name))};
+                }
+                let edges = edges.into_iter().map(|edge| (edge.source_id, edge.target_id, edge.directed)).collect::<Vec<_>>();
+                // A swapped undirected edge must not land on the key of a directed one in the opposite order
+                if let Some((source_id, target_id)) = rename_edge_conflict(&edges, &old_name, &new_name) {
+                    let other = if source_id == new_name { target_id } else { source_id };
+                    return ModResponse {error: Some(format!(
+                        "'{}' has a directed and an undirected edge with '{}' that would share a key under the new name, delete or change one of them first",
+                        old_name, other
+                    ))};
                 }
+                let latest_revision = match db
+                    .revision()
+                    .find_first(vec![revision::entity::equals(RevisionEntity::Node.to_string()), revision::entity_key::equals(old_name.clone())])
+                    .order_by(revision::number::order(Direction::Desc))
+                    .exec()
+                    .await
+                {
+                    Ok(latest) => latest.map_or(0, |revision| revision.number),
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let after = node::Data {name: new_name.clone(), ..before.clone()};
+                let before_value = serde_json::to_value(&before).unwrap_or_default();
+                let after_value = serde_json::to_value(&after).unwrap_or_default();
+                // Nodes without history get their state before the rename as baseline, otherwise the upsert leaves the latest revision as it is
+                let baseline_number = latest_revision.max(1);
+                // One transaction. Edges, articles, translations, links, aliases and the author/tag links follow the
+                // key through ON UPDATE CASCADE, undirected edges are swapped where the new name changes their order.
+                // The swap compares in byte order like canonical_edge_key, not in the collation of the database.
+                // The revision history moves along and gets the rename as revision of its own
+                let db_response = db
+                    ._batch((
+                        db.node().update(node::name::equals(old_name.clone()), vec![node::name::set(new_name.clone())]),
+                        db._execute_raw(raw!(
+                            r#"UPDATE "Edge" SET source_id = target_id, target_id = source_id WHERE directed = false AND source_id COLLATE "C" > target_id COLLATE "C" AND (source_id = {} OR target_id = {})"#,
+                            PrismaValue::String(new_name.clone()),
+                            PrismaValue::String(new_name.clone())
+                        )),
+                        db.node_alias().delete_many(vec![node_alias::normalized::equals(normalize_alias(&new_name))]),
+                        db.node_redirect().delete_many(vec![node_redirect::old_name::equals(new_name.clone())]),
+                        db.node_redirect().create(old_name.clone(), node::name::equals(new_name.clone()), vec![]),
+                        db.revision().update_many(
+                            vec![revision::entity::equals(RevisionEntity::Node.to_string()), revision::entity_key::equals(old_name.clone())],
+                            vec![revision::entity_key::set(new_name.clone())]
+                        ),
+                        db.revision().upsert(
+                            revision::entity_entity_key_number(RevisionEntity::Node.to_string(), new_name.clone(), baseline_number),
+                            (
+                                RevisionEntity::Node.to_string(),
+                                new_name.clone(),
+                                baseline_number,
+                                before_value.clone(),
+                                BASELINE_REVISION_AUTHOR.to_string(),
+                                vec![]
+                            ),
+                            vec![]
+                        ),
+                        db.revision().create(
+                            RevisionEntity::Node.to_string(),
+                            new_name.clone(),
+                            baseline_number + 1,
+                            after_value.clone(),
+                            principal.actor(),
+                            vec![]
+                        ),
+                        db.audit_log().create(
+                            principal.actor(),
+                            "Rename".to_string(),
+                            RevisionEntity::Node.to_string(),
+                            new_name,
+                            json_diff(&before_value, &after_value),
+                            vec![]
+                        ),
+                    ))
+                    .await;
+                let error = db_response.err().map(|e| e.to_string());
+                ModResponse {error}
+            })
+        })
+        .query("modEdge", |t| {
+            t(|AuthCtx { db, principal, .. }, mod_edge_request: ModEdgeRequest| async move {
                 let db = db.lock().await;
                 let data = mod_edge_request.data;
+                // Undirected edges are addressed in either order, the stored key may be the reverse one
+                let (source_id, target_id) = match resolve_edge_key(&db, &data.source_id, &data.target_id, data.directed).await {
+                    Ok(key) => key.unwrap_or_else(|| canonical_edge_key(data.source_id.clone(), data.target_id.clone(), data.directed)),
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                let new_key = canonical_edge_key(data.source_id, data.target_id, data.directed);
+                let entity_key = format!("{}->{}", source_id, target_id);
+                let before = db.edge().find_unique(edge::source_id_target_id(source_id.clone(), target_id.clone())).exec().await.ok().flatten();
+                let status = write_status(&principal, data.status, None, before.as_ref().map(|before| before.status));
+                let action = mod_edge_request.op.to_string();
+                let mut update_values = vec![edge::status::set(status), edge::directed::set(data.directed)];
+                if new_key != (source_id.clone(), target_id.clone()) {
+                    // Changing the direction can change the order, articles follow the key
+                    update_values.push(edge::source_id::set(new_key.0.clone()));
+                    update_values.push(edge::target_id::set(new_key.1.clone()));
+                }
                 let update_param = (
-                    edge::source_id_target_id(data.source_id.clone(), data.target_id.clone()),
-                    vec![]
+                    edge::source_id_target_id(source_id, target_id),
+                    update_values
                 );
                 let create_param = (
-                    node::name::equals(data.source_id),
-                    node::name::equals(data.target_id),
-                    vec![]
+                    node::name::equals(new_key.0),
+                    node::name::equals(new_key.1),
+                    vec![edge::status::set(status), edge::directed::set(data.directed)]
                 );
                 let db_response = match mod_edge_request.op {
                     ModType::Create => {
@@ -744,45 +2928,76 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                         .await
                     }
                 };
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_edge_request.op, ModType::Delete)).then_some(row);
+                        record_audit(&db, &principal.actor(), &action, "Edge", entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
         .query("modArticle", |t| {
-            t(|(db, _, _), mod_article_request: ModArticleRequest| async move {
-                if !check_admin(&mod_article_request.token) {
-                    return ModResponse {error: Some("Invalid token".to_string())};
-                }
+            t(|AuthCtx { db, principal, .. }, mod_article_request: ModArticleRequest| async move {
                 let db = db.lock().await;
                 let data = mod_article_request.data;
+                let entity_key = data.name.clone();
+                let before = db.article().find_unique(article::name::equals(data.name.clone())).exec().await.ok().flatten();
+                // Articles on the reverse of an undirected edge belong to that edge
+                let (edge_source_id, edge_target_id) = match resolve_edge_key(&db, &data.edge_source_id, &data.edge_target_id, true).await {
+                    Ok(key) => key.unwrap_or((data.edge_source_id, data.edge_target_id)),
+                    Err(e) => return ModResponse {error: Some(e.to_string())},
+                };
+                if let Some(current) = before.as_ref().filter(|before| matches!(mod_article_request.op, ModType::Update | ModType::Upsert) && needs_proposal(&principal, before.status)) {
+                    // The published article stays online, the change waits for reviewContent
+                    let proposed = article::Data {status: current.status, edge_source_id, edge_target_id, ..data};
+                    let error = propose_change(&db, &principal, RevisionEntity::Article, entity_key, current, &proposed).await.err().map(|e| e.to_string());
+                    return ModResponse {error};
+                }
+                let status = write_status(&principal, data.status, data.publish_at, before.as_ref().map(|before| before.status));
+                let action = mod_article_request.op.to_string();
                 let update_param = (
                     article::name::equals(data.name.clone()),
                     vec![
-                        article::edge_source_id::set(data.edge_source_id.clone()),
-                        article::edge_target_id::set(data.edge_target_id.clone()),
+                        article::edge_source_id::set(edge_source_id.clone()),
+                        article::edge_target_id::set(edge_target_id.clone()),
                         article::discipline_id::set(data.discipline_id.clone()),
-                        article::author::set(data.author.clone()),
                         article::teaser_text::set(data.teaser_text.clone()),
                         article::date::set(data.date.clone()),
                         article::url::set(data.url.clone()),
                         article::views::set(data.views.clone()),
                         article::wlk_hovers::set(data.wlk_hovers),
                         article::wlk_clicks::set(data.wlk_clicks),
-                        article::read_time::set(data.read_time.clone()),
+                        article::read_time_minutes::set(data.read_time_minutes),
+                        article::digital_specificity::set(data.digital_specificity),
+                        article::analog_comparison::set(data.analog_comparison),
+                        article::societal_relevance::set(data.societal_relevance),
+                        article::enabler::set(data.enabler),
+                        article::status::set(status),
+                        article::publish_at::set(data.publish_at),
+                        article::unpublish_at::set(data.unpublish_at),
                     ]
                 );
                 let create_param = (
                     data.name.clone(),
-                    edge::source_id_target_id(data.edge_source_id.clone(), data.edge_target_id.clone()),
+                    edge::source_id_target_id(edge_source_id, edge_target_id),
                     discipline::name::equals(data.discipline_id),
                     vec![
-                        article::author::set(data.author.clone()),
                         article::teaser_text::set(data.teaser_text.clone()),
                         article::date::set(data.date.clone()),
                         article::url::set(data.url.clone()),
                         article::views::set(data.views.clone()),
                         article::wlk_hovers::set(data.wlk_hovers),
                         article::wlk_clicks::set(data.wlk_clicks),
-                        article::read_time::set(data.read_time.clone()),
+                        article::read_time_minutes::set(data.read_time_minutes),
+                        article::digital_specificity::set(data.digital_specificity),
+                        article::analog_comparison::set(data.analog_comparison),
+                        article::societal_relevance::set(data.societal_relevance),
+                        article::enabler::set(data.enabler),
+                        article::status::set(status),
+                        article::publish_at::set(data.publish_at),
+                        article::unpublish_at::set(data.unpublish_at),
                     ]
                 );
                 let db_response = match mod_article_request.op {
@@ -824,11 +3039,19 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
                         .await
                     }
                 };
-                ModResponse {error: db_response.err().map_or(None, |e|Some(e.to_string()))}
+                let error = match db_response {
+                    Ok(row) => {
+                        let after = (!matches!(mod_article_request.op, ModType::Delete)).then_some(row);
+                        record_revisioned_change(&db, &principal.actor(), &action, &RevisionEntity::Article.to_string(), entity_key, before.as_ref(), after.as_ref()).await.err().map(|e| e.to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                ModResponse {error}
             })
         })
         .build()
         .arced();
+    let cookie_jwt_config = jwt_config.clone();
     // Exposed router INFO: does not work with new version of axum
     Router::new()
         .route(
@@ -841,15 +3064,710 @@ pub async fn create_route(db_param: Arc<Mutex<db::PrismaClient>>) -> Router {
         .route(
             "/rspc/:id",
             rspc_router
-                .endpoint(|headers: HeaderMap, method: Method| {
+                .endpoint(move |headers: HeaderMap, method: Method, ConnectInfo(addr): ConnectInfo<SocketAddr>| {
                     //println!("Requested {} '{}'", method, path); // Logging
-                    (db_param, headers, method)
+                    let client_ip = client_ip(&headers, addr, trust_proxy_headers);
+                    (db_param, jwt_config, mailer, headers, method, client_ip)
                 })
                 .axum(),
         )
+        .route(
+            "/session",
+            post(move |headers: HeaderMap| {
+                let response = open_cookie_session(&cookie_jwt_config, &headers);
+                async move { response }
+            })
+            .delete(|| async { (StatusCode::NO_CONTENT, [(SET_COOKIE, session_cookie("", chrono::Duration::zero()))]) }),
+        )
     //Router::new().route("/node", get(handle_node_get))
 }
 
+/// Puts the access token of the Authorization header into the HttpOnly session cookie, for browser clients that
+/// shouldn't keep it readable by scripts. The cookie lives as long as the token, so it is set again after each refresh
+fn open_cookie_session(jwt_config: &JwtConfig, headers: &HeaderMap) -> Response {
+    let token = match session_token(headers, &[]) {
+        Some(token) if !is_api_key(token) => token,
+        _ => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    let claims = match decode_jwt(jwt_config, token) {
+        Ok(token_data) => token_data.claims,
+        Err(_) => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    let max_age = chrono::Duration::seconds(claims.exp as i64 - Utc::now().timestamp());
+    (StatusCode::NO_CONTENT, [(SET_COOKIE, session_cookie(token, max_age))]).into_response()
+}
+
+/// Opens a new session for a user and returns its access and refresh token
+async fn create_session(db: &db::PrismaClient, jwt_config: &JwtConfig, user: &users::Data) -> Result<LoginResponse, rspc::Error> {
+    let now = Utc::now();
+    // Expired and revoked sessions of this user are of no further use
+    let cutoff: DateTime<FixedOffset> = now.into();
+    db
+        .session()
+        .delete_many(vec![
+            session::user_email::equals(user.email.clone()),
+            or![
+                session::revoked::equals(true),
+                session::expires_at::lt(cutoff),
+            ],
+        ])
+        .exec()
+        .await?;
+    let refresh_token = gen_token();
+    let session = db
+        .session()
+        .create(
+            users::email::equals(user.email.clone()),
+            hash_token(&refresh_token),
+            (now + jwt_config.refresh_expiry()).into(),
+            vec![]
+        )
+        .exec()
+        .await?;
+    let token = gen_jwt(jwt_config, &user.email, &user.role, &session.id)
+        .map_err(|e| rspc::Error::new(ErrorCode::InternalServerError, e.to_string()))?;
+    Ok(LoginResponse {token: Some(token), refresh_token: Some(refresh_token), must_change_password: user.must_change_password, retry_after: None, mfa_token: None, must_enroll_totp: totp_required(&user.role) && !user.totp_enabled})
+}
+
+/// Revokes all sessions of a user, optionally keeping the current one
+async fn revoke_sessions(db: &db::PrismaClient, email: &str, keep_session: Option<&str>) -> Result<(), rspc::Error> {
+    let mut filter = vec![session::user_email::equals(email.to_string())];
+    if let Some(keep_session) = keep_session {
+        filter.push(session::id::not(keep_session.to_string()));
+    }
+    db
+        .session()
+        .update_many(filter, vec![session::revoked::set(true)])
+        .exec()
+        .await?;
+    Ok(())
+}
+
+fn throttle_key_email(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
+
+fn throttle_key_ip(ip: &IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn throttle_limit(key: &str) -> &'static LoginLimit {
+    if key.starts_with("ip:") { &IP_LOGIN_LIMIT } else { &ACCOUNT_LOGIN_LIMIT }
+}
+
+/// Client address of a request, taken from X-Forwarded-For only if the proxy headers are trusted
+fn client_ip(headers: &HeaderMap, addr: SocketAddr, trust_proxy_headers: bool) -> IpAddr {
+    if trust_proxy_headers {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    addr.ip()
+}
+
+async fn record_login_attempt(db: &db::PrismaClient, email: &str, ip: &IpAddr, success: bool) -> Result<(), rspc::Error> {
+    db
+        .login_attempt()
+        .create(email.to_string(), ip.to_string(), success, vec![])
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Display texts of the given nodes in the requested locale (or German)
+async fn node_translations(db: &db::PrismaClient, names: Vec<String>, locale: Locale) -> Result<HashMap<String, node_translation::Data>, QueryError> {
+    let rows = db
+        .node_translation()
+        .find_many(vec![
+            node_translation::node_name::in_vec(names),
+            node_translation::locale::in_vec(vec![locale, FALLBACK_LOCALE]),
+        ])
+        .exec()
+        .await?;
+    Ok(pick_translations(rows.into_iter().map(|row| (row.node_name.clone(), row.locale, row)), locale))
+}
+
+/// Display texts of the given articles in the requested locale (or German)
+async fn article_translations(db: &db::PrismaClient, names: Vec<String>, locale: Locale) -> Result<HashMap<String, article_translation::Data>, QueryError> {
+    let rows = db
+        .article_translation()
+        .find_many(vec![
+            article_translation::article_name::in_vec(names),
+            article_translation::locale::in_vec(vec![locale, FALLBACK_LOCALE]),
+        ])
+        .exec()
+        .await?;
+    Ok(pick_translations(rows.into_iter().map(|row| (row.article_name.clone(), row.locale, row)), locale))
+}
+
+/// Display names of the given categories in the requested locale (or German)
+async fn category_translations(db: &db::PrismaClient, names: Vec<String>, locale: Locale) -> Result<HashMap<String, String>, QueryError> {
+    let rows = db
+        .category_translation()
+        .find_many(vec![
+            category_translation::category_name::in_vec(names),
+            category_translation::locale::in_vec(vec![locale, FALLBACK_LOCALE]),
+        ])
+        .exec()
+        .await?;
+    Ok(pick_translations(rows.into_iter().map(|row| (row.category_name, row.locale, row.display_name)), locale))
+}
+
+/// Display names of the given disciplines in the requested locale (or German)
+async fn discipline_translations(db: &db::PrismaClient, names: Vec<String>, locale: Locale) -> Result<HashMap<String, String>, QueryError> {
+    let rows = db
+        .discipline_translation()
+        .find_many(vec![
+            discipline_translation::discipline_name::in_vec(names),
+            discipline_translation::locale::in_vec(vec![locale, FALLBACK_LOCALE]),
+        ])
+        .exec()
+        .await?;
+    Ok(pick_translations(rows.into_iter().map(|row| (row.discipline_name, row.locale, row.display_name)), locale))
+}
+
+/// Untranslated texts keep the stable key or the original teaser text
+async fn localize_nodes(db: &db::PrismaClient, nodes: Vec<node::Data>, locale: Locale) -> Result<Vec<LocalizedNode>, QueryError> {
+    let translations = node_translations(db, nodes.iter().map(|node| node.name.clone()).collect(), locale).await?;
+    let categories = category_translations(db, nodes.iter().map(|node| node.category_id.clone()).collect(), locale).await?;
+    Ok(nodes
+        .into_iter()
+        .map(|mut node| {
+            let translation = translations.get(&node.name);
+            if let Some(teaser_text) = translation.and_then(|translation| translation.teaser_text.clone()) {
+                node.teaser_text = teaser_text;
+            }
+            LocalizedNode {
+                display_name: translation.map_or_else(|| node.name.clone(), |translation| translation.display_name.clone()),
+                category_display_name: categories.get(&node.category_id).cloned().unwrap_or_else(|| node.category_id.clone()),
+                node,
+            }
+        })
+        .collect())
+}
+
+/// Untranslated texts keep the stable key or the original teaser text
+async fn localize_articles(db: &db::PrismaClient, articles: Vec<article::Data>, locale: Locale) -> Result<Vec<LocalizedArticle>, QueryError> {
+    let translations = article_translations(db, articles.iter().map(|article| article.name.clone()).collect(), locale).await?;
+    let disciplines = discipline_translations(db, articles.iter().map(|article| article.discipline_id.clone()).collect(), locale).await?;
+    Ok(articles
+        .into_iter()
+        .map(|mut article| {
+            let translation = translations.get(&article.name);
+            if let Some(teaser_text) = translation.and_then(|translation| translation.teaser_text.clone()) {
+                article.teaser_text = teaser_text;
+            }
+            LocalizedArticle {
+                display_name: translation.map_or_else(|| article.name.clone(), |translation| translation.display_name.clone()),
+                discipline_display_name: disciplines.get(&article.discipline_id).cloned().unwrap_or_else(|| article.discipline_id.clone()),
+                article,
+            }
+        })
+        .collect())
+}
+
+/// Without the publish permission writes go to draft, so they only go live after a review.
+/// Reviewed content keeps its status, it is never taken offline by an edit. Published content with a future publish date is scheduled instead
+fn write_status(principal: &Principal, requested: PublicationStatus, publish_at: Option<DateTime<FixedOffset>>, current: Option<PublicationStatus>) -> PublicationStatus {
+    if principal.has_permission(Permission::Publish) {
+        scheduled_status(requested, publish_at, Utc::now().into())
+    } else {
+        match current {
+            Some(reviewed @ (PublicationStatus::Published | PublicationStatus::Scheduled)) => reviewed,
+            _ => PublicationStatus::Draft,
+        }
+    }
+}
+
+/// Changes to published or scheduled nodes and articles by principals without the publish permission are only proposed, see propose_change
+fn needs_proposal(principal: &Principal, current: PublicationStatus) -> bool {
+    matches!(current, PublicationStatus::Published | PublicationStatus::Scheduled) && !principal.has_permission(Permission::Publish)
+}
+
+fn article_date_filter(date_from: Option<DateTime<FixedOffset>>, date_to: Option<DateTime<FixedOffset>>) -> Vec<article::WhereParam> {
+    let mut filter = vec![];
+    if let Some(date_from) = date_from {
+        filter.push(article::date::gte(date_from));
+    }
+    if let Some(date_to) = date_to {
+        filter.push(article::date::lte(date_to));
+    }
+    filter
+}
+
+fn node_read_time_filter(min_read_time: Option<i32>, max_read_time: Option<i32>) -> Vec<node::WhereParam> {
+    let mut filter = vec![];
+    if let Some(min_read_time) = min_read_time {
+        filter.push(node::read_time_minutes::gte(min_read_time));
+    }
+    if let Some(max_read_time) = max_read_time {
+        filter.push(node::read_time_minutes::lte(max_read_time));
+    }
+    filter
+}
+
+fn article_read_time_filter(min_read_time: Option<i32>, max_read_time: Option<i32>) -> Vec<article::WhereParam> {
+    let mut filter = vec![];
+    if let Some(min_read_time) = min_read_time {
+        filter.push(article::read_time_minutes::gte(min_read_time));
+    }
+    if let Some(max_read_time) = max_read_time {
+        filter.push(article::read_time_minutes::lte(max_read_time));
+    }
+    filter
+}
+
+/// Public nodes, see schedule::is_visible
+fn visible_node(now: DateTime<FixedOffset>) -> node::WhereParam {
+    and![
+        node::status::in_vec(vec![PublicationStatus::Published, PublicationStatus::Scheduled]),
+        or![node::publish_at::equals(None), node::publish_at::lte(now)],
+        or![node::unpublish_at::equals(None), node::unpublish_at::gt(now)],
+    ]
+}
+
+/// Public articles, see schedule::is_visible
+fn visible_article(now: DateTime<FixedOffset>) -> article::WhereParam {
+    and![
+        article::status::in_vec(vec![PublicationStatus::Published, PublicationStatus::Scheduled]),
+        or![article::publish_at::equals(None), article::publish_at::lte(now)],
+        or![article::unpublish_at::equals(None), article::unpublish_at::gt(now)],
+    ]
+}
+
+/// Moves content to another publication status, optionally only from the given one
+async fn set_publication_status(db: &db::PrismaClient, principal: &Principal, content: ContentRef, status: PublicationStatus, only_from: Option<PublicationStatus>, action: &str) -> Result<(), rspc::Error> {
+    let check_transition = |current: PublicationStatus| match only_from {
+        Some(from) if from != current => Err(rspc::Error::new(
+            ErrorCode::BadRequest,
+            format!("Content is {:?}, expected {:?}", current, from),
+        )),
+        _ => Ok(()),
+    };
+    let not_found = || rspc::Error::new(ErrorCode::NotFound, "Content not found".into());
+    let now: DateTime<FixedOffset> = Utc::now().into();
+    match content {
+        ContentRef::Node { name } => {
+            let before = db.node().find_unique(node::name::equals(name.clone())).exec().await?.ok_or_else(not_found)?;
+            check_transition(before.status)?;
+            let after = db
+                .node()
+                .update(node::name::equals(name.clone()), vec![node::status::set(scheduled_status(status, before.publish_at, now))])
+                .exec()
+                .await?;
+            record_revisioned_change(db, &principal.actor(), action, &RevisionEntity::Node.to_string(), name, Some(&before), Some(&after)).await?;
+        }
+        ContentRef::Edge { source_id, target_id } => {
+            // Undirected edges are addressed in either order, like in modEdge
+            let (source_id, target_id) = resolve_edge_key(db, &source_id, &target_id, true).await?.ok_or_else(not_found)?;
+            let before = db
+                .edge()
+                .find_unique(edge::source_id_target_id(source_id.clone(), target_id.clone()))
+                .exec()
+                .await?
+                .ok_or_else(not_found)?;
+            check_transition(before.status)?;
+            let after = db
+                .edge()
+                .update(edge::source_id_target_id(source_id.clone(), target_id.clone()), vec![edge::status::set(status)])
+                .exec()
+                .await?;
+            record_audit(db, &principal.actor(), action, "Edge", format!("{}->{}", source_id, target_id), Some(&before), Some(&after)).await?;
+        }
+        ContentRef::Article { name } => {
+            let before = db.article().find_unique(article::name::equals(name.clone())).exec().await?.ok_or_else(not_found)?;
+            check_transition(before.status)?;
+            let after = db
+                .article()
+                .update(article::name::equals(name.clone()), vec![article::status::set(scheduled_status(status, before.publish_at, now))])
+                .exec()
+                .await?;
+            record_revisioned_change(db, &principal.actor(), action, &RevisionEntity::Article.to_string(), name, Some(&before), Some(&after)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Stores a change to reviewed content as pending revision. The row stays as it is until reviewContent applies the change
+async fn propose_change<T: Serialize>(db: &db::PrismaClient, principal: &Principal, entity: RevisionEntity, entity_key: String, current: &T, proposed: &T) -> Result<(), QueryError> {
+    record_audit(db, &principal.actor(), "Propose", &entity.to_string(), entity_key.clone(), Some(current), Some(proposed)).await?;
+    record_revision(db, &principal.actor(), &entity.to_string(), &entity_key, Some(current), Some(proposed), true).await
+}
+
+/// Applies (approve) or discards the latest pending revision of a node or article, older ones are superseded by it.
+/// Returns false if there is none, the content is then reviewed as a whole
+async fn review_proposal(db: &db::PrismaClient, principal: &Principal, content: &ContentRef, approve: bool, action: &str) -> Result<bool, rspc::Error> {
+    let (entity, entity_key) = match content {
+        ContentRef::Node { name } => (RevisionEntity::Node, name.clone()),
+        ContentRef::Article { name } => (RevisionEntity::Article, name.clone()),
+        ContentRef::Edge { .. } => return Ok(false),
+    };
+    let pending_filter = || vec![
+        revision::entity::equals(entity.to_string()),
+        revision::entity_key::equals(entity_key.clone()),
+        revision::pending::equals(true),
+    ];
+    let proposal = match db.revision().find_first(pending_filter()).order_by(revision::number::order(Direction::Desc)).exec().await? {
+        Some(proposal) => proposal,
+        None => return Ok(false),
+    };
+    if approve {
+        let invalid_proposal = |e: serde_json::Error| rspc::Error::new(ErrorCode::InternalServerError, format!("Revision {} can't be applied: {}", proposal.number, e));
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        match entity {
+            RevisionEntity::Node => {
+                let mut proposed: node::Data = serde_json::from_value(current_snapshot(db, entity, &entity_key, proposal.data).await?).map_err(invalid_proposal)?;
+                proposed.status = scheduled_status(PublicationStatus::Published, proposed.publish_at, now);
+                let before = db.node().find_unique(node::name::equals(entity_key.clone())).exec().await?;
+                let after = restore_node(db, proposed).await?;
+                db.revision().delete_many(pending_filter()).exec().await?;
+                record_revisioned_change(db, &principal.actor(), action, &entity.to_string(), entity_key, before.as_ref(), Some(&after)).await?;
+            }
+            RevisionEntity::Article => {
+                let mut proposed: article::Data = serde_json::from_value(current_snapshot(db, entity, &entity_key, proposal.data).await?).map_err(invalid_proposal)?;
+                proposed.status = scheduled_status(PublicationStatus::Published, proposed.publish_at, now);
+                let before = db.article().find_unique(article::name::equals(entity_key.clone())).exec().await?;
+                let after = restore_article(db, proposed).await?;
+                db.revision().delete_many(pending_filter()).exec().await?;
+                record_revisioned_change(db, &principal.actor(), action, &entity.to_string(), entity_key, before.as_ref(), Some(&after)).await?;
+            }
+        }
+    } else {
+        db.revision().delete_many(pending_filter()).exec().await?;
+        record_audit(db, &principal.actor(), action, &entity.to_string(), entity_key, Some(&proposal.data), None).await?;
+    }
+    Ok(true)
+}
+
+/// Revision data in terms of the current names, as renamed nodes leave the snapshots as they were (see rekey_snapshot).
+/// Old node names are followed through their redirect, the edge of an article is looked up in either order
+/// because the rename can have swapped an undirected one
+async fn current_snapshot(db: &db::PrismaClient, entity: RevisionEntity, entity_key: &str, mut data: serde_json::Value) -> Result<serde_json::Value, QueryError> {
+    let node_fields: &[&str] = match entity {
+        RevisionEntity::Node => &[],
+        RevisionEntity::Article => &["edge_source_id", "edge_target_id"],
+    };
+    let names = node_fields
+        .iter()
+        .filter_map(|field| data.get(*field).and_then(serde_json::Value::as_str).map(str::to_string))
+        .collect::<Vec<_>>();
+    let current_names = db
+        .node_redirect()
+        .find_many(vec![node_redirect::old_name::in_vec(names)])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|redirect| (redirect.old_name, redirect.node_name))
+        .collect::<HashMap<_, _>>();
+    rekey_snapshot(&mut data, entity_key, node_fields, &current_names);
+    if let (Some(source_id), Some(target_id)) = (data["edge_source_id"].as_str(), data["edge_target_id"].as_str()) {
+        if let Some((source_id, target_id)) = resolve_edge_key(db, source_id, target_id, false).await? {
+            data["edge_source_id"] = source_id.into();
+            data["edge_target_id"] = target_id.into();
+        }
+    }
+    Ok(data)
+}
+
+async fn find_revision(db: &db::PrismaClient, entity: RevisionEntity, entity_key: &str, number: i32) -> Result<revision::Data, rspc::Error> {
+    db
+        .revision()
+        .find_unique(revision::entity_entity_key_number(entity.to_string(), entity_key.to_string(), number))
+        .exec()
+        .await?
+        .ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, format!("Revision {} not found", number)))
+}
+
+/// Writes every field of a node snapshot back, recreating the node if it was deleted since
+async fn restore_node(db: &db::PrismaClient, data: node::Data) -> Result<node::Data, QueryError> {
+    db
+        .node()
+        .upsert(
+            node::name::equals(data.name.clone()),
+            (
+                data.name.clone(),
+                data.url.clone(),
+                category::name::equals(data.category_id.clone()),
+                vec![
+                    node::teaser_text::set(data.teaser_text.clone()),
+                    node::views::set(data.views),
+                    node::wlk_hovers::set(data.wlk_hovers),
+                    node::wlk_clicks::set(data.wlk_clicks),
+                    node::read_time_minutes::set(data.read_time_minutes),
+                    node::status::set(data.status),
+                    node::publish_at::set(data.publish_at),
+                    node::unpublish_at::set(data.unpublish_at),
+                ]
+            ),
+            vec![
+                node::status::set(data.status),
+                node::publish_at::set(data.publish_at),
+                node::unpublish_at::set(data.unpublish_at),
+                node::url::set(data.url),
+                node::category_id::set(data.category_id),
+                node::teaser_text::set(data.teaser_text),
+                node::views::set(data.views),
+                node::wlk_hovers::set(data.wlk_hovers),
+                node::wlk_clicks::set(data.wlk_clicks),
+                node::read_time_minutes::set(data.read_time_minutes),
+            ]
+        )
+        .exec()
+        .await
+}
+
+/// Writes every field of an article snapshot back, recreating the article if it was deleted since
+async fn restore_article(db: &db::PrismaClient, data: article::Data) -> Result<article::Data, QueryError> {
+    db
+        .article()
+        .upsert(
+            article::name::equals(data.name.clone()),
+            (
+                data.name.clone(),
+                edge::source_id_target_id(data.edge_source_id.clone(), data.edge_target_id.clone()),
+                discipline::name::equals(data.discipline_id.clone()),
+                vec![
+                    article::teaser_text::set(data.teaser_text.clone()),
+                    article::date::set(data.date.clone()),
+                    article::url::set(data.url.clone()),
+                    article::views::set(data.views),
+                    article::wlk_hovers::set(data.wlk_hovers),
+                    article::wlk_clicks::set(data.wlk_clicks),
+                    article::read_time_minutes::set(data.read_time_minutes),
+                    article::digital_specificity::set(data.digital_specificity),
+                    article::analog_comparison::set(data.analog_comparison),
+                    article::societal_relevance::set(data.societal_relevance),
+                    article::enabler::set(data.enabler),
+                    article::status::set(data.status),
+                    article::publish_at::set(data.publish_at),
+                    article::unpublish_at::set(data.unpublish_at),
+                ]
+            ),
+            vec![
+                article::status::set(data.status),
+                article::publish_at::set(data.publish_at),
+                article::unpublish_at::set(data.unpublish_at),
+                article::edge_source_id::set(data.edge_source_id),
+                article::edge_target_id::set(data.edge_target_id),
+                article::discipline_id::set(data.discipline_id),
+                article::teaser_text::set(data.teaser_text),
+                article::date::set(data.date),
+                article::url::set(data.url),
+                article::views::set(data.views),
+                article::wlk_hovers::set(data.wlk_hovers),
+                article::wlk_clicks::set(data.wlk_clicks),
+                article::read_time_minutes::set(data.read_time_minutes),
+                article::digital_specificity::set(data.digital_specificity),
+                article::analog_comparison::set(data.analog_comparison),
+                article::societal_relevance::set(data.societal_relevance),
+                article::enabler::set(data.enabler),
+            ]
+        )
+        .exec()
+        .await
+}
+
+/// Records the successful login and opens a new session
+async fn complete_login(db: &db::PrismaClient, jwt_config: &JwtConfig, user: &users::Data, client_ip: &IpAddr) -> Result<LoginResponse, rspc::Error> {
+    record_login_attempt(db, &user.email, client_ip, true).await?;
+    // Only the account counter is reset, one valid account must not unlock a guessing client
+    db
+        .login_throttle()
+        .delete_many(vec![login_throttle::key::equals(throttle_key_email(&user.email))])
+        .exec()
+        .await?;
+    create_session(db, jwt_config, user).await
+}
+
+/// Accepts an authenticator code or an unused recovery code, each only once
+async fn claim_totp_code(db: &db::PrismaClient, user: &users::Data, code: &str) -> Result<bool, rspc::Error> {
+    let last_step = user.totp_last_step.map(i64::from);
+    let step = user.totp_secret.as_deref().and_then(|secret| totp::verify_code(secret, code, Utc::now().timestamp(), last_step));
+    if let Some(step) = step {
+        // Conditional, so two concurrent logins can't both use the same code
+        let claimed = db
+            .users()
+            .update_many(
+                vec![
+                    users::email::equals(user.email.clone()),
+                    or![users::totp_last_step::equals(None), users::totp_last_step::lt(step as i32)],
+                ],
+                vec![users::totp_last_step::set(Some(step as i32))]
+            )
+            .exec()
+            .await?;
+        return Ok(claimed == 1)
+    }
+    let now: DateTime<FixedOffset> = Utc::now().into();
+    let claimed = db
+        .recovery_code()
+        .update_many(
+            vec![
+                recovery_code::code_hash::equals(hash_token(&totp::normalize_recovery_code(code))),
+                recovery_code::user_email::equals(user.email.clone()),
+                recovery_code::used_at::equals(None),
+            ],
+            vec![recovery_code::used_at::set(Some(now))]
+        )
+        .exec()
+        .await?;
+    Ok(claimed == 1)
+}
+
+/// Seconds until the longest running lockout of the given throttle keys ends, if any
+async fn login_retry_after(db: &db::PrismaClient, keys: &[String]) -> Result<Option<i64>, rspc::Error> {
+    let now = Utc::now();
+    let cutoff: DateTime<FixedOffset> = now.into();
+    let throttles = db
+        .login_throttle()
+        .find_many(vec![
+            login_throttle::key::in_vec(keys.to_vec()),
+            login_throttle::locked_until::gt(cutoff),
+        ])
+        .exec()
+        .await?;
+    Ok(throttles
+        .into_iter()
+        .filter_map(|throttle| throttle.locked_until)
+        .map(|locked_until| (locked_until.with_timezone(&Utc) - now).num_seconds() + 1)
+        .max())
+}
+
+/// Counts a failed login against every key and locks them once their limit is exceeded.
+/// Returns the resulting lockout in seconds, if any
+async fn register_login_failure(db: &db::PrismaClient, keys: &[String]) -> Result<Option<i64>, rspc::Error> {
+    let now = Utc::now();
+    let mut retry_after = None;
+    for key in keys {
+        let limit = throttle_limit(key);
+        let previous_failures = db
+            .login_throttle()
+            .find_unique(login_throttle::key::equals(key.clone()))
+            .exec()
+            .await?
+            // Failures older than the window are forgiven
+            .filter(|throttle| now.signed_duration_since(throttle.last_failure) < chrono::Duration::seconds(limit.window_seconds))
+            .map_or(0, |throttle| throttle.failures);
+        let failures = previous_failures + 1;
+        let lockout = lockout_duration(limit, failures);
+        if let Some(duration) = lockout {
+            retry_after = retry_after.max(Some(duration.num_seconds()));
+        }
+        let locked_until: Option<DateTime<FixedOffset>> = lockout.map(|duration| (now + duration).into());
+        db
+            .login_throttle()
+            .upsert(
+                login_throttle::key::equals(key.clone()),
+                login_throttle::create(
+                    key.clone(),
+                    vec![
+                        login_throttle::failures::set(failures),
+                        login_throttle::locked_until::set(locked_until),
+                    ]
+                ),
+                vec![
+                    login_throttle::failures::set(failures),
+                    login_throttle::last_failure::set(now.into()),
+                    login_throttle::locked_until::set(locked_until),
+                ]
+            )
+            .exec()
+            .await?;
+    }
+    Ok(retry_after)
+}
+
+/// Stores a new password reset token for the user, replacing unused ones. Returns the token to mail
+async fn create_password_reset(db: &db::PrismaClient, email: &str) -> Result<String, rspc::Error> {
+    db
+        .password_reset()
+        .delete_many(vec![
+            password_reset::user_email::equals(email.to_string()),
+            password_reset::used_at::equals(None),
+        ])
+        .exec()
+        .await?;
+    let token = gen_token();
+    db
+        .password_reset()
+        .create(
+            hash_token(&token),
+            users::email::equals(email.to_string()),
+            (Utc::now() + chrono::Duration::minutes(PASSWORD_RESET_MINUTES)).into(),
+            vec![]
+        )
+        .exec()
+        .await?;
+    Ok(token)
+}
+
+/// Authenticates a session token: the signature is not enough, the session may have been revoked since
+async fn session_principal(db: &db::PrismaClient, jwt_config: &JwtConfig, token: &str, now: DateTime<FixedOffset>, path: &str) -> Result<Principal, rspc::Error> {
+    let mut claims = decode_jwt(jwt_config, token)
+        .map_err(|err| rspc::Error::new(ErrorCode::Unauthorized, err.to_string()))?
+        .claims;
+    let user = db
+        .session()
+        .find_first(vec![
+            session::id::equals(claims.sid.clone()),
+            session::revoked::equals(false),
+            session::expires_at::gt(now),
+            session::user::is(vec![users::disabled::equals(false)]),
+        ])
+        .with(session::user::fetch())
+        .exec()
+        .await?
+        .and_then(|active_session| active_session.user().ok().cloned())
+        .ok_or_else(|| rspc::Error::new(ErrorCode::Unauthorized, "Session revoked".into()))?;
+    // Role changes take effect immediately instead of with the next token
+    claims.role = user.role;
+    if user.must_change_password && !matches!(path, "changePassword" | "logout") {
+        return Err(rspc::Error::new(ErrorCode::Forbidden, "Password change required".into()));
+    }
+    if totp_required(&user.role) && !user.totp_enabled && !matches!(path, "totpEnroll" | "totpConfirm" | "logout") {
+        return Err(rspc::Error::new(ErrorCode::Forbidden, "Two-factor authentication required".into()));
+    }
+    Ok(Principal::User(claims))
+}
+
+/// Authenticates an API key, only active (not revoked or expired) keys are accepted
+async fn api_key_principal(db: &db::PrismaClient, key: &str, now: DateTime<FixedOffset>) -> Result<Principal, rspc::Error> {
+    let api_key = db
+        .api_key()
+        .find_first(vec![
+            api_key::key_hash::equals(hash_token(key)),
+            api_key::revoked_at::equals(None),
+            or![api_key::expires_at::equals(None), api_key::expires_at::gt(now)],
+        ])
+        .exec()
+        .await?
+        .ok_or_else(|| rspc::Error::new(ErrorCode::Unauthorized, "Invalid API key".into()))?;
+    db
+        .api_key()
+        .update(api_key::id::equals(api_key.id.clone()), vec![api_key::last_used_at::set(Some(now))])
+        .exec()
+        .await?;
+    Ok(Principal::ApiKey { id: api_key.id, name: api_key.name, scopes: api_key.scopes })
+}
+
+/// Account procedures only make sense for a signed in user, not for API keys
+fn session_claims(principal: &Principal) -> Result<&Claims, rspc::Error> {
+    principal.claims().ok_or_else(|| rspc::Error::new(ErrorCode::Forbidden, "Only available with a user session".into()))
+}
+
+/// Admins must not lock themselves out through the user management procedures
+fn reject_self(principal: &Principal, email: &str) -> Result<(), rspc::Error> {
+    if principal.claims().map_or(false, |claims| claims.email == email) {
+        return Err(rspc::Error::new(ErrorCode::BadRequest, "Use another admin account to change your own account".into()));
+    }
+    Ok(())
+}
+
 enum AppError {
     PrismaError(QueryError),
     NotFound,
@@ -0,0 +1,106 @@
+use std::{sync::Arc, time::Duration};
+
+use prisma_client_rust::{chrono::{DateTime, FixedOffset, Utc}, QueryError};
+use tokio::sync::Mutex;
+
+use crate::{audit::record_revisioned_change, db::{article, node, PrismaClient, PublicationStatus}};
+
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+/// Actor of the audit records and revisions written by the scheduler
+const SCHEDULER_ACTOR: &str = "scheduler";
+
+/// Public visibility of a node or article. Scheduled content shows up once its window started,
+/// so the public queries are exact to the second even between two scheduler runs
+pub fn is_visible(status: PublicationStatus, publish_at: Option<DateTime<FixedOffset>>, unpublish_at: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>) -> bool {
+    matches!(status, PublicationStatus::Published | PublicationStatus::Scheduled)
+        && !matches!(publish_at, Some(publish_at) if publish_at > now)
+        && !matches!(unpublish_at, Some(unpublish_at) if unpublish_at <= now)
+}
+
+/// Approved content with a publish date in the future waits as scheduled
+pub fn scheduled_status(status: PublicationStatus, publish_at: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>) -> PublicationStatus {
+    match (status, publish_at) {
+        (PublicationStatus::Published, Some(publish_at)) if publish_at > now => PublicationStatus::Scheduled,
+        _ => status,
+    }
+}
+
+/// Keeps the stored status in line with the publication windows, so the admin views show what is public
+pub async fn run(db: Arc<Mutex<PrismaClient>>) {
+    let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = apply_schedule(&*db.lock().await, Utc::now().into()).await {
+            println!("Applying the publication schedule failed: {}", e);
+        }
+    }
+}
+
+async fn apply_schedule(db: &PrismaClient, now: DateTime<FixedOffset>) -> Result<(), QueryError> {
+    // Expired first, content whose whole window has passed goes straight back to draft. The window is cleared with it,
+    // otherwise approving the content again would publish it while the past unpublish_at still hides it
+    let expired_nodes = db
+        .node()
+        .find_many(vec![
+            node::status::in_vec(vec![PublicationStatus::Published, PublicationStatus::Scheduled]),
+            node::unpublish_at::lte(now),
+        ])
+        .exec()
+        .await?;
+    for expired in expired_nodes {
+        set_node_status(db, expired, vec![node::status::set(PublicationStatus::Draft), node::unpublish_at::set(None)]).await?;
+    }
+    let due_nodes = db
+        .node()
+        .find_many(vec![
+            node::status::equals(PublicationStatus::Scheduled),
+            node::publish_at::lte(now),
+        ])
+        .exec()
+        .await?;
+    for due in due_nodes {
+        set_node_status(db, due, vec![node::status::set(PublicationStatus::Published)]).await?;
+    }
+
+    let expired_articles = db
+        .article()
+        .find_many(vec![
+            article::status::in_vec(vec![PublicationStatus::Published, PublicationStatus::Scheduled]),
+            article::unpublish_at::lte(now),
+        ])
+        .exec()
+        .await?;
+    for expired in expired_articles {
+        set_article_status(db, expired, vec![article::status::set(PublicationStatus::Draft), article::unpublish_at::set(None)]).await?;
+    }
+    let due_articles = db
+        .article()
+        .find_many(vec![
+            article::status::equals(PublicationStatus::Scheduled),
+            article::publish_at::lte(now),
+        ])
+        .exec()
+        .await?;
+    for due in due_articles {
+        set_article_status(db, due, vec![article::status::set(PublicationStatus::Published)]).await?;
+    }
+    Ok(())
+}
+
+async fn set_node_status(db: &PrismaClient, before: node::Data, changes: Vec<node::SetParam>) -> Result<(), QueryError> {
+    let after = db
+        .node()
+        .update(node::name::equals(before.name.clone()), changes)
+        .exec()
+        .await?;
+    record_revisioned_change(db, SCHEDULER_ACTOR, "Schedule", "Node", after.name.clone(), Some(&before), Some(&after)).await
+}
+
+async fn set_article_status(db: &PrismaClient, before: article::Data, changes: Vec<article::SetParam>) -> Result<(), QueryError> {
+    let after = db
+        .article()
+        .update(article::name::equals(before.name.clone()), changes)
+        .exec()
+        .await?;
+    record_revisioned_change(db, SCHEDULER_ACTOR, "Schedule", "Article", after.name.clone(), Some(&before), Some(&after)).await
+}
@@ -0,0 +1,92 @@
+use std::env;
+
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use ring::{constant_time, hmac, rand::{self, SecureRandom}};
+
+// RFC 6238 defaults, the only parameters most authenticator apps support
+const STEP_SECONDS: i64 = 30;
+const DIGITS: usize = 6;
+const SECRET_BYTES: usize = 20;
+/// Codes of the neighbouring steps are accepted as well, to tolerate clock drift
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+const DEFAULT_ISSUER: &str = "bidt Glossar";
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_BYTES: usize = 10;
+const RECOVERY_CODE_GROUP: usize = 4;
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system randomness available");
+    bytes
+}
+
+/// New shared secret, base32 encoded as authenticator apps expect it
+pub fn gen_secret() -> String {
+    BASE32_NOPAD.encode(&random_bytes::<SECRET_BYTES>())
+}
+
+/// RFC 4226 HOTP value for the given counter
+pub fn hotp(secret: &[u8], counter: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let digest = hmac::sign(&key, &counter.to_be_bytes());
+    let digest = digest.as_ref();
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    format!("{:0width$}", value % 10u32.pow(DIGITS as u32), width = DIGITS)
+}
+
+pub fn time_step(unix_time: i64) -> i64 {
+    unix_time.div_euclid(STEP_SECONDS)
+}
+
+/// Checks a code against the secret and returns the matching time step.
+/// Steps up to `last_step` were already used and are rejected, so a code can't be replayed
+pub fn verify_code(secret: &str, code: &str, unix_time: i64, last_step: Option<i64>) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    let current = time_step(unix_time);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .filter(|step| *step >= 0 && !matches!(last_step, Some(last) if *step <= last))
+        .find(|step| constant_time::verify_slices_are_equal(hotp(&secret, *step as u64).as_bytes(), code.as_bytes()).is_ok())
+}
+
+/// `otpauth://` URI for authenticator apps, usually shown as QR code. The issuer is taken from `TOTP_ISSUER`
+pub fn provisioning_uri(account: &str, secret: &str) -> String {
+    let issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| DEFAULT_ISSUER.to_string());
+    let issuer = utf8_percent_encode(&issuer, NON_ALPHANUMERIC).to_string();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(account, NON_ALPHANUMERIC),
+        secret,
+        issuer,
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+/// One-time codes to sign in without the authenticator, formatted in groups (XXXX-XXXX-XXXX-XXXX)
+pub fn gen_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = BASE32_NOPAD.encode(&random_bytes::<RECOVERY_CODE_BYTES>());
+            code.as_bytes()
+                .chunks(RECOVERY_CODE_GROUP)
+                .map(|group| String::from_utf8_lossy(group).into_owned())
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Recovery codes are compared without grouping and case, as users tend to retype them
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
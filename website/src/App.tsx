@@ -31,9 +31,19 @@ let theme = createTheme({
 });
 theme = responsiveFontSizes(theme);
 
+// Session token sent with every request, admin procedures are rejected without it
+let sessionToken: string | null = null;
+export const setSessionToken = (token: string | null) => { sessionToken = token; };
+
+const authorizedFetch = (input: RequestInfo, init?: RequestInit) => {
+  const headers = new Headers(init?.headers);
+  if (sessionToken) headers.set("Authorization", `Bearer ${sessionToken}`);
+  return fetch(input, { ...init, headers });
+};
+
 // RSPC API client
 export const apiClient = createClient<Procedures>({
-  transport: new FetchTransport("http://localhost:5000/api/rspc"),
+  transport: new FetchTransport("http://localhost:5000/api/rspc", authorizedFetch),
 });
 
 /**
@@ -0,0 +1,80 @@
+import React, { useEffect, useState } from "react";
+import { Box, Button, Stack, TextField, Typography } from "@mui/material";
+import { ErrorOutline } from "@mui/icons-material";
+import { apiClient } from "../../App";
+import { TotpEnrollResponse } from "../../types/bindings";
+
+type AccountSetupFormProps = {
+  mustChangePassword: boolean,
+  mustEnrollTotp: boolean,
+  onDone: () => void,
+}
+
+/**
+ * Steps the server requires after login before the admin panel can be used:
+ * changing an initial password and setting up two-factor authentication
+ */
+export const AccountSetupForm: React.FC<AccountSetupFormProps> = (props) => {
+  const [passwordChanged, setPasswordChanged] = useState(!props.mustChangePassword);
+  const [currentPassword, setCurrentPassword] = useState("");
+  const [newPassword, setNewPassword] = useState("");
+  const [enrollment, setEnrollment] = useState<TotpEnrollResponse | null>(null);
+  const [totpCode, setTotpCode] = useState("");
+  const [recoveryCodes, setRecoveryCodes] = useState<string[] | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    if (passwordChanged && !props.mustEnrollTotp) props.onDone();
+    if (passwordChanged && props.mustEnrollTotp && enrollment === null) {
+      apiClient.query(["totpEnroll"]).then(setEnrollment).catch(e => setError(e.message));
+    }
+  }, [passwordChanged]); // eslint-disable-line react-hooks/exhaustive-deps
+
+  const changePasswordHandler = () => {
+    apiClient.query(["changePassword", { current_password: currentPassword, new_password: newPassword }])
+      .then(() => { setError(null); setPasswordChanged(true); })
+      .catch(e => setError(e.message));
+  };
+
+  const confirmTotpHandler = () => {
+    apiClient.query(["totpConfirm", { code: totpCode }])
+      .then(codes => { setError(null); setRecoveryCodes(codes); })
+      .catch(e => setError(e.message));
+  };
+
+  return <Box sx={{ my: 8, mx: 4, display: "flex", flexDirection: "column", alignItems: "center" }}>
+    {!passwordChanged && <>
+      <Typography variant="h5">{"Passwort ändern"}</Typography>
+      <Typography variant="body1">{"Das Passwort muss vor der weiteren Nutzung geändert werden."}</Typography>
+      <TextField value={currentPassword} onChange={(e) => setCurrentPassword(e.target.value)} margin="normal" fullWidth label="Aktuelles Passwort" type="password" />
+      <TextField value={newPassword} onChange={(e) => setNewPassword(e.target.value)} margin="normal" fullWidth label="Neues Passwort" type="password" />
+      <Button disabled={currentPassword === "" || newPassword === ""} variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onClick={changePasswordHandler}>
+        {"Passwort ändern"}
+      </Button>
+    </>}
+    {passwordChanged && enrollment && recoveryCodes === null && <>
+      <Typography variant="h5">{"Zwei-Faktor-Authentifizierung einrichten"}</Typography>
+      <Typography variant="body1">{"Den Schlüssel in einer Authenticator-App hinzufügen und den angezeigten Code eingeben:"}</Typography>
+      <Typography variant="body1" sx={{ fontFamily: "monospace", my: 1 }}>{enrollment.secret}</Typography>
+      <Typography variant="body2" sx={{ wordBreak: "break-all" }}>{enrollment.uri}</Typography>
+      <TextField value={totpCode} onChange={(e) => setTotpCode(e.target.value)} margin="normal" fullWidth label="Code" />
+      <Button disabled={totpCode === ""} variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onClick={confirmTotpHandler}>
+        {"Bestätigen"}
+      </Button>
+    </>}
+    {recoveryCodes && <>
+      <Typography variant="h5">{"Wiederherstellungscodes"}</Typography>
+      <Typography variant="body1">{"Diese Codes werden nur jetzt angezeigt. Jeder Code ersetzt einmalig einen Authenticator-Code."}</Typography>
+      {recoveryCodes.map(code => <Typography key={code} sx={{ fontFamily: "monospace" }}>{code}</Typography>)}
+      <Button variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onClick={props.onDone}>
+        {"Weiter"}
+      </Button>
+    </>}
+    {error && <Stack direction={"row"} sx={{ placeItems: "center center" }} gap="1vw">
+      <ErrorOutline color="error"></ErrorOutline>
+      <Typography color="error">{error}</Typography>
+    </Stack>}
+  </Box>
+}
+
+export default AccountSetupForm;
@@ -17,11 +17,7 @@ import { DisciplineForm } from "./DisciplineForm";
 
 import { saveAs } from 'file-saver';
 
-type AdminPanelProps = {
-  apiToken: string
-}
-
-export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
+export const AdminPanel: React.FC = () => {
   const [loading, setLoading] = useState(true);
 
   const [nodes, setNodes] = useState<Node[]>([]);
@@ -114,7 +110,7 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
   });
   const handleRemoveNodeConfirm = () => {
     if (targetNode) {
-      apiClient.query(["modNode", { data: targetNode, op: "Delete", token: props.apiToken }])
+      apiClient.query(["modNode", { data: targetNode, op: "Delete" }])
         .then(() => { fetchNodes(); setNodeDeletionDialog(false); setTargetNode(undefined); })
     }
   };
@@ -125,7 +121,7 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
   });
   const handleRemoveArticleConfirm = () => {
     if (targetArticle) {
-      apiClient.query(["modArticle", { data: targetArticle, op: "Delete", token: props.apiToken }])
+      apiClient.query(["modArticle", { data: targetArticle, op: "Delete" }])
         .then(() => { fetchEdges(); setArticleDeletionDialog(false); setTargetArticle(undefined); })
     }
   };
@@ -198,11 +194,11 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
         const nodeData = JSON.parse(t);
         // Upload categories
         await Promise.all(nodeData.categories.map((category: Category) => {
-          return apiClient.query(["modCategory", { data: category, op: "Upsert", token: props.apiToken }]).catch(e => console.log(e))
+          return apiClient.query(["modCategory", { data: category, op: "Upsert" }]).catch(e => console.log(e))
         }));
         // Upload nodes
         await Promise.all(nodeData.nodes.map((node: Node) => {
-          return apiClient.query(["modNode", { data: node, op: "Upsert", token: props.apiToken }]).catch(e => console.log(e))
+          return apiClient.query(["modNode", { data: node, op: "Upsert" }]).catch(e => console.log(e))
         }));
         fetchNodes();
         fetchCategories();
@@ -218,15 +214,15 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
         console.log("printing things")
         // Upload disciplines
         await Promise.all(edgeData.disciplines.map((discipline: Discipline) => {
-          return apiClient.query(["modDiscipline", { data: discipline, op: "Upsert", token: props.apiToken }]).catch(e => console.log(e))
+          return apiClient.query(["modDiscipline", { data: discipline, op: "Upsert" }]).catch(e => console.log(e))
         }));
         // Upload edges
         await Promise.all(edgeData.edges.map((edge: Edge) => {
-          return apiClient.query(["modEdge", { data: edge, op: "Upsert", token: props.apiToken }]).catch(e => console.log(e))
+          return apiClient.query(["modEdge", { data: edge, op: "Upsert" }]).catch(e => console.log(e))
         }));
         // Upload articles
         await Promise.all(edgeData.articles.map((article: Article) => {
-          return apiClient.query(["modArticle", { data: article, op: "Upsert", token: props.apiToken }]).catch(e => console.log(e))
+          return apiClient.query(["modArticle", { data: article, op: "Upsert" }]).catch(e => console.log(e))
         }));
         fetchEdges();
         fetchDisciplines();
@@ -523,7 +519,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
           onSubmit={() => { fetchNodes(); setNodeEditorDialog(false); setTargetNode(undefined); }}
           target={targetNode}
           categories={nodeCategories}
-          apiToken={props.apiToken}
         />
       </DialogContent>
     </Dialog>
@@ -535,7 +530,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
           target={targetArticle}
           disciplines={edgeDisciplines}
           edges={edges}
-          apiToken={props.apiToken}
         />
       </DialogContent>
     </Dialog>
@@ -585,7 +579,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
     <Dialog open={edgeDeletionDialog} onClose={() => setEdgeDeletionDialog(false)}>
       <DialogContent>
         <EdgeDeletionForm
-          apiToken={props.apiToken}
           articles={articles}
           edges={edges}
           onAbort={() => setEdgeDeletionDialog(false)}
@@ -596,7 +589,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
     <Dialog open={edgeAdditionDialog} onClose={() => setEdgeAdditionDialog(false)}>
       <DialogContent>
         <EdgeCreationForm
-          apiToken={props.apiToken}
           edges={edges}
           nodes={nodes}
           onAbort={() => setEdgeAdditionDialog(false)}
@@ -607,7 +599,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
     <Dialog open={categoryAdditionDialog} onClose={() => setCategoryAdditionDialog(false)}>
       <DialogContent>
         <CategoryForm
-          apiToken={props.apiToken}
           categories={nodeCategories}
           onAbort={() => setCategoryAdditionDialog(false)}
           onSubmit={() => { fetchCategories(); setCategoryAdditionDialog(false); }}
@@ -617,7 +608,6 @@ export const AdminPanel: React.FC<AdminPanelProps> = (props) => {
     <Dialog open={disciplineAdditionDialog} onClose={() => setDisciplineAdditionDialog(false)}>
       <DialogContent>
         <DisciplineForm
-          apiToken={props.apiToken}
           disciplines={edgeDisciplines}
           onAbort={() => setDisciplineAdditionDialog(false)}
           onSubmit={() => { fetchDisciplines(); setDisciplineAdditionDialog(false); }}
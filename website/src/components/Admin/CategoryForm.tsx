@@ -8,7 +8,6 @@ type CategoryFormProps = {
   onSubmit: () => void,
   onAbort: () => void,
   categories: Category[],
-  apiToken: string,
 }
 
 const baseCategory: Category = {
@@ -17,7 +16,7 @@ const baseCategory: Category = {
 
 export const CategoryForm: React.FC<CategoryFormProps> = (props) => {
   const handleConfirm = () => {
-    apiClient.query(["modCategory", { data: localCategory, op: "Create", token: props.apiToken }]).then(props.onSubmit)
+    apiClient.query(["modCategory", { data: localCategory, op: "Create" }]).then(props.onSubmit)
   }
   const handleCancel = () => {
     props.onAbort();
@@ -8,7 +8,6 @@ type DisciplineFormProps = {
   onSubmit: () => void,
   onAbort: () => void,
   disciplines: Discipline[],
-  apiToken: string,
 }
 
 const baseCategory: Category = {
@@ -17,7 +16,7 @@ const baseCategory: Category = {
 
 export const DisciplineForm: React.FC<DisciplineFormProps> = (props) => {
   const handleConfirm = () => {
-    apiClient.query(["modDiscipline", { data: localDiscipline, op: "Create", token: props.apiToken }]).then(props.onSubmit)
+    apiClient.query(["modDiscipline", { data: localDiscipline, op: "Create" }]).then(props.onSubmit)
   }
   const handleCancel = () => {
     props.onAbort();
@@ -10,7 +10,6 @@ type EdgeArticleFormProps = {
   onAbort: () => void,
   disciplines: Discipline[],
   edges: EdgeWithArticlesInfo[],
-  apiToken: string,
   target?: Article,
 }
 
@@ -30,7 +29,7 @@ const defaultArticle: Article = {
 }
 export const EdgeArticleForm: React.FC<EdgeArticleFormProps> = (props) => {
   const handleConfirm = () => {
-    apiClient.query(["modArticle", { data: localArticle, op: props.target ? "Update" : "Create", token: props.apiToken }]).then(props.onSubmit)
+    apiClient.query(["modArticle", { data: localArticle, op: props.target ? "Update" : "Create" }]).then(props.onSubmit)
   }
   const handleCancel = () => {
     props.onAbort();
@@ -8,7 +8,6 @@ type EdgeCreationFormProps = {
   onAbort: () => void,
   edges: EdgeWithArticlesInfo[],
   nodes: Node[],
-  apiToken: string,
 }
 
 const baseEdge: Edge = {
@@ -18,7 +17,7 @@ const baseEdge: Edge = {
 
 export const EdgeCreationForm: React.FC<EdgeCreationFormProps> = (props) => {
   const handleConfirm = () => {
-      apiClient.query(["modEdge", { data: localEdge, op: "Create", token: props.apiToken }]).then(props.onSubmit)
+      apiClient.query(["modEdge", { data: localEdge, op: "Create" }]).then(props.onSubmit)
   }
   const handleCancel = () => {
     props.onAbort();
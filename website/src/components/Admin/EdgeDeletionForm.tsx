@@ -9,13 +9,12 @@ type EdgeDeletionFormProps = {
   onAbort: () => void,
   edges: EdgeWithArticlesInfo[],
   articles: Article[],
-  apiToken: string,
 }
 
 export const EdgeDeletionForm: React.FC<EdgeDeletionFormProps> = (props) => {
   const handleConfirm = () => {
     if (selectedEdge) {
-      apiClient.query(["modEdge", { data: selectedEdge, op: "Delete", token: props.apiToken }]).then(props.onSubmit)
+      apiClient.query(["modEdge", { data: selectedEdge, op: "Delete" }]).then(props.onSubmit)
     }
   }
   const handleCancel = () => {
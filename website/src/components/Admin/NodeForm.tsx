@@ -9,7 +9,6 @@ type NodeFormProps = {
   onSubmit: () => void,
   onAbort: () => void,
   categories: Category[],
-  apiToken: string,
   target?: Node,
 }
 
@@ -27,7 +26,7 @@ const defaultNode: Node = {
 
 export const NodeForm: React.FC<NodeFormProps> = (props) => {
   const handleConfirm = () => {
-    apiClient.query(["modNode", {data: localNode, op: props.target ? "Update" : "Create", token: props.apiToken}]).then(props.onSubmit)
+    apiClient.query(["modNode", {data: localNode, op: props.target ? "Update" : "Create"}]).then(props.onSubmit)
   }
 
   const handleCancel = () => {
@@ -1,14 +1,18 @@
 import { AppBar, Box, Button, Checkbox, CssBaseline, FormControlLabel, Grid, IconButton, Paper, TextField, Toolbar, Typography, Avatar, Tooltip, Dialog, DialogContent, Stack } from "@mui/material";
-import React, { useState } from "react";
-import { DeleteForever, Logout, Refresh, SupervisorAccount } from "@mui/icons-material";
+import React, { useEffect, useState } from "react";
+import { DeleteForever, ErrorOutline, Logout, Refresh, SupervisorAccount } from "@mui/icons-material";
 import backgroundImg from ".././assets/images/bidt-bg.png";
 import AdminPanel from "../components/Admin/AdminPanel";
-import { apiClient } from "../App";
+import AccountSetupForm from "../components/Admin/AccountSetupForm";
+import { apiClient, setSessionToken } from "../App";
 import { LoginResponse } from "../types/bindings";
 import { Footer } from "../components/Footer";
 import jwt_decode from 'jwt-decode';
 
 const LOGIN_TOKEN_KEY = "loginToken";
+const REFRESH_TOKEN_KEY = "refreshToken";
+/** Seconds before expiry the access token is renewed */
+const REFRESH_MARGIN = 60;
 type TokenType = {
   email: string,
   role: string,
@@ -35,31 +39,90 @@ export const AdminPage: React.FC = () => {
     }
     return tkn
   };
-  const [token, setToken] = useState<string | null>(loadToken());
+  const [token, setTokenState] = useState<string | null>(() => {
+    const tkn = loadToken();
+    setSessionToken(tkn);
+    return tkn;
+  });
+  const setToken = (tkn: string | null) => {
+    setSessionToken(tkn);
+    setTokenState(tkn);
+  };
+  const [refreshToken, setRefreshToken] = useState<string | null>(() => localStorage.getItem(REFRESH_TOKEN_KEY));
   const [login, setLogin] = useState<boolean>(true); //true shows login, false register
   const [username, setUsername] = useState<string>("");
   const [password, setPassword] = useState<string>("");
-  const [rememberUser, setRememberUser] = useState(false);
+  const [inviteCode, setInviteCode] = useState<string>("");
+  const [rememberUser, setRememberUser] = useState(localStorage.getItem(REFRESH_TOKEN_KEY) !== null);
+  const [mfaToken, setMfaToken] = useState<string | null>(null); // password was right, second factor pending
+  const [totpCode, setTotpCode] = useState<string>("");
+  const [mustChangePassword, setMustChangePassword] = useState(false);
+  const [mustEnrollTotp, setMustEnrollTotp] = useState(false);
+  const [loginError, setLoginError] = useState<string | null>(null);
 
   const [adminPanelKey, setAdminPanelKey] = useState("0");
   const [deleteAllDialog, setDeleteAllDialog] = useState(false);
   const [deleteAllVerification, setDeleteAllVerification] = useState("");
 
-  const submitHandler = async () => {
-    const response = await apiClient.query([
-      login ? "login" : "register",
-      { email: username, password: password },
-    ]);
-    console.log(response);
-    if (login) {
-      const token = (response as LoginResponse).token
-      setToken(token);
-      if (token && rememberUser) localStorage.setItem(LOGIN_TOKEN_KEY, token);
+  const clearSession = () => {
+    setToken(null);
+    setRefreshToken(null);
+    setMfaToken(null);
+    localStorage.removeItem(LOGIN_TOKEN_KEY);
+    localStorage.removeItem(REFRESH_TOKEN_KEY);
+  };
+
+  const handleLoginResponse = (response: LoginResponse) => {
+    if (response.token) {
+      setToken(response.token);
+      setRefreshToken(response.refresh_token);
+      setMfaToken(null);
+      setLoginError(null);
+      setMustChangePassword(response.must_change_password);
+      setMustEnrollTotp(response.must_enroll_totp);
+      if (rememberUser) {
+        localStorage.setItem(LOGIN_TOKEN_KEY, response.token);
+        if (response.refresh_token) localStorage.setItem(REFRESH_TOKEN_KEY, response.refresh_token);
+      }
+    } else if (response.mfa_token) {
+      setMfaToken(response.mfa_token);
+      setLoginError(null);
+    } else if (response.retry_after !== null) {
+      setLoginError(`Zu viele Fehlversuche, erneut versuchen in ${Math.ceil(Number(response.retry_after) / 60)} Minuten`);
+    } else {
+      setLoginError("Anmeldung fehlgeschlagen");
     }
   };
 
+  const submitHandler = async () => {
+    const request = mfaToken
+      ? apiClient.query(["loginTotp", { mfa_token: mfaToken, code: totpCode }])
+      : login
+        ? apiClient.query(["login", { email: username, password: password }])
+        : apiClient.query(["register", { email: username, password: password, invite_code: inviteCode }]);
+    await request.then(handleLoginResponse).catch(e => setLoginError(e.message));
+    setTotpCode("");
+  };
+
+  const logoutHandler = async () => {
+    await apiClient.query(["logout"]).catch(e => console.log(e));
+    clearSession();
+  };
+
+  // The access token is short-lived, renew it with the refresh token shortly before it expires
+  useEffect(() => {
+    if (!refreshToken) return;
+    const expiresIn = token ? (jwt_decode<TokenType>(token).exp - REFRESH_MARGIN) * 1000 - Date.now() : 0;
+    const timer = setTimeout(() => {
+      apiClient.query(["refresh", { refresh_token: refreshToken }])
+        .then(handleLoginResponse)
+        .catch(() => clearSession());
+    }, Math.max(expiresIn, 0));
+    return () => clearTimeout(timer);
+  }, [token, refreshToken]); // eslint-disable-line react-hooks/exhaustive-deps
+
   const deleteAllHandler = async () => {
-    await apiClient.query(["deleteAllData", { token: token || "" }]).catch(e => console.log(e))
+    await apiClient.query(["deleteAllData"]).catch(e => console.log(e))
     setDeleteAllDialog(false)
     setAdminPanelKey(adminPanelKey === "0" ? "1" : "0") // refresh page
   };
@@ -112,13 +175,18 @@ export const AdminPage: React.FC = () => {
                 </Tooltip>
                 <Tooltip title={"Abmelden"}>
                   <IconButton>
-                    <Logout onClick={() => { setToken(null); localStorage.removeItem(LOGIN_TOKEN_KEY); }} />
+                    <Logout onClick={logoutHandler} />
                   </IconButton>
                 </Tooltip>
               </div>
             </Toolbar>
           </AppBar>
-          <AdminPanel apiToken={token} key={"panel-" + adminPanelKey}></AdminPanel>
+          {mustChangePassword || mustEnrollTotp ?
+            <Box component="main" sx={{ flexGrow: 1, mt: 8 }}>
+              <AccountSetupForm mustChangePassword={mustChangePassword} mustEnrollTotp={mustEnrollTotp} onDone={() => { setMustChangePassword(false); setMustEnrollTotp(false); }} />
+            </Box>
+            :
+            <AdminPanel key={"panel-" + adminPanelKey}></AdminPanel>}
         </Box>
         :
         <Grid container component="main" sx={{ height: "100vh" }}>
@@ -129,8 +197,25 @@ export const AdminPage: React.FC = () => {
                 <SupervisorAccount />
               </Avatar>
               <Typography variant="h5">
-                {login ? "Anmelden" : "Neuen Nutzer registrieren"}
+                {mfaToken ? "Zwei-Faktor-Code eingeben" : login ? "Anmelden" : "Neuen Nutzer registrieren"}
               </Typography>
+              {mfaToken ?
+              <Box sx={{ mt: 1 }}>
+                <TextField
+                  value={totpCode}
+                  onChange={(e) => setTotpCode(e.target.value)}
+                  margin="normal"
+                  fullWidth
+                  label="Code aus der Authenticator-App oder Wiederherstellungscode"
+                />
+                <Button disabled={totpCode === ""} fullWidth variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onMouseUp={() => submitHandler()} >
+                  {"Anmelden"}
+                </Button>
+                <Typography variant="body1" onClick={() => setMfaToken(null)} style={{ cursor: "pointer", textDecoration: "underline" }} >
+                  {"Zur Anmeldung"}
+                </Typography>
+              </Box>
+              :
               <Box sx={{ mt: 1 }}>
                 <TextField
                   value={username}
@@ -147,6 +232,15 @@ export const AdminPage: React.FC = () => {
                   label="Password"
                   type="password"
                 />
+                {!login &&
+                  <TextField
+                    value={inviteCode}
+                    onChange={(e) => setInviteCode(e.target.value)}
+                    margin="normal"
+                    fullWidth
+                    label="Einladungscode"
+                  />
+                }
                 <div style={{ display: "flex", gap: "1vw", margin: "1vw 1vh" }}>
                   <Typography variant="body1">
                     {login ? "Neu hier?" : "Bereits existierender Nutzer?"}
@@ -155,19 +249,23 @@ export const AdminPage: React.FC = () => {
                     {login ? "Account registrieren" : "Zur Anmeldung"}
                   </Typography>
                 </div>
-                <Button disabled={password === "" || username === ""} fullWidth variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onMouseUp={() => submitHandler()} >
+                <Button disabled={password === "" || username === "" || (!login && inviteCode === "")} fullWidth variant="contained" sx={{ mt: 3, mb: 2, background: "#39D098" }} onMouseUp={() => submitHandler()} >
                   {login ? "Anmelden" : "Registrieren"}
                 </Button>
                 {login &&
                   <FormControlLabel
-                    control={<Checkbox value="remember" color="primary" />}
+                    control={<Checkbox value="remember" checked={rememberUser} color="primary" />}
                     label="Anmeldedaten merken"
                     value={rememberUser}
                     onChange={(_e, checked) => setRememberUser(checked)}
                   />
                 }
-                <Footer />
-              </Box>
+              </Box>}
+              {loginError && <Stack direction={"row"} sx={{ placeItems: "center center" }} gap="1vw">
+                <ErrorOutline color="error"></ErrorOutline>
+                <Typography color="error">{loginError}</Typography>
+              </Stack>}
+              <Footer />
             </Box>
           </Grid>
         </Grid>}